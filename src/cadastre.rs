@@ -1,103 +1,577 @@
 
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{de, Serialize, Deserialize, Serializer, Deserializer};
 use crate::{
   pos::Pos,
-  parcel::{Parcel, PLOT_WIDTH, PLOT_HEIGHT},
+  parcel::{Parcel, HtmlLineOptions, PLOT_WIDTH, PLOT_HEIGHT, escape_html},
   owner::Owner,
-  background::Background
+  background::Background,
+  report::{Report, ReportKind},
+  transfer::Transfer,
+  charset::Charset
 };
 
+/// The number of plots that are rendered horizontally
+pub const TOWN_WIDTH: usize = 25;
+/// The number of plots that are rendered vertically
+pub const TOWN_HEIGHT: usize = 25;
+
+/// The whole town: all claimed plots, and the seed for the background of unclaimed plots
+/// This is what gets stored in the town json
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cadastre {
 	// seed: u64,
 	places: HashMap<PosKey, Parcel>,
 	#[serde(rename="seed")]
-	background: Background
+	background: Background,
+	/// The number of updates that have been done since the cadastre was created
+	#[serde(default)]
+	generation: u64,
+	#[serde(default)]
+	history: Vec<HistoryEvent>,
+	/// The characters that were allowed in the parcels when the town was built
+	#[serde(default)]
+	charset: Charset
 }
 
 impl Cadastre {
+	/// A town without any plots
 	pub fn empty() -> Self {
-		Self { places: HashMap::new(), background: Background(1) }
+		Self { places: HashMap::new(), background: Background(1), generation: 0, history: Vec::new(), charset: Charset::default() }
 	}
 
-	pub fn build(old: &Self, parcels: impl Iterator<Item=Parcel>) -> Self {
+	/// Build a new town from the parcels that users want to claim
+	/// The previous town is used to decide conflicts: the user that held a plot before gets to keep it
+	/// Parcels that can't be placed are added to the report
+	pub fn build(old: &Self, parcels: impl Iterator<Item=Parcel>, rules: &BuildRules, report: &mut Report) -> Self {
+		let parcels: Vec<Parcel> = parcels.collect();
+		// A transfer applies to a plot when the giver held it previously and the recipient claims it now
+		// The recipient then gets the tenancy of that plot, above the giver, who keeps theirs in case the claim of the recipient fails
+		// A transfer with a location takes precedence over one without for the same plot
+		let mut transfers: HashMap<PosKey, &Transfer> = HashMap::new();
+		for transfer in &rules.transfers {
+			let covered = old.places.iter()
+				.filter(|(_, held)| transfer.covers(held))
+				.filter(|(_, held)| parcels.iter().any(|parcel| parcel.location == held.location && transfer.is_claimed_by(parcel)));
+			for (key, _) in covered {
+				if transfers.get(key).is_none_or(|existing| existing.location.is_none()) {
+					transfers.insert(*key, transfer);
+				}
+			}
+		}
+		let tenancy = |parcel: &Parcel| -> u8 {
+			if transfers.get(&PosKey::from_pos(parcel.location)).is_some_and(|transfer| transfer.is_claimed_by(parcel)) {
+				2
+			} else if old.parcel(parcel.location).is_some_and(|held| held.is_same_plot(parcel)) {
+				1
+			} else {
+				0
+			}
+		};
+		// Plots that are already held (or transferred) are placed first, so a new parcel of the same user can't take the quota that the held plot needs
+		// The sort is stable, so otherwise the order in which the parcels were read still decides
+		let mut ordered: Vec<&Parcel> = parcels.iter().collect();
+		ordered.sort_by_key(|parcel| tenancy(parcel) == 0);
 		let mut places: HashMap<PosKey, Parcel> = HashMap::new();
-		for parcel in parcels {
-			// When multiple plots are trying to claim the same space, the owner with the highest priority should win
-			// Admins have highest priority, then users, then public plots
-			// If the priority is equal, then the one who held the plot previously will get this
-			// If neither owner held the plot previously, then it doesn't matter
-			// If both the existing parcel and the new parcel have the same owner (eg. public), then it doesn't matter either
-			let key = PosKey::from_pos(parcel.location);
-			let can_claim: bool =
+		// A parcel over the quota is tried again when another parcel of the same user has been displaced since, as that frees up the quota
+		let mut waiting: Vec<Parcel> = ordered.into_iter().cloned().collect();
+		loop {
+			let count = waiting.len();
+			let mut over_quota: Vec<Parcel> = Vec::new();
+			for parcel in waiting {
+				// When multiple plots are trying to claim the same space, the owner with the highest priority should win
+				// Admins have highest priority, then users, then public plots
+				// Inactive parcels have lower priority than active parcels of the same kind of owner
+				// If the priority is equal, then the one who held the plot previously will get this
+				// Shared plots have tenancy if any of its users held the plot previously
+				// Additional parcels of the same user are tracked by name, so only the parcel that held this spot has tenancy
+				// When the plot is transferred, the recipient's tenancy goes above that of the previous holder
+				// If neither owner held the plot previously, then it doesn't matter
+				// If both the existing parcel and the new parcel have the same owner (eg. public), then it doesn't matter either
+				let key = PosKey::from_pos(parcel.location);
 				if let Some(conflict) = places.get(&key) {
-					match parcel.owner.priority().cmp(&conflict.owner.priority()) {
+					let can_claim = match parcel.priority().cmp(&conflict.priority()) {
 						Ordering::Greater => true,
-						Ordering::Equal => old.owner_of(parcel.location).is_some_and(|owner| owner == parcel.owner),
+						Ordering::Equal => tenancy(&parcel) > tenancy(conflict),
 						Ordering::Less => false
+					};
+					if !can_claim {
+						report.add(parcel.owner.clone(), Some(parcel.location), ReportKind::LostConflict(conflict.owner.clone()));
+						continue;
 					}
-				} else {
-					true
-				};
-			if can_claim {
-				places.insert(key, parcel);
+				}
+				// Users can only hold a limited number of plots
+				// Shared plots count for the user whose parcel file it is
+				// Plots of admins and public plots are not limited
+				if let Some(holder) = parcel.owner.quota_holder() {
+					let held = places.iter()
+						.filter(|(k, p)| **k != key && p.owner.quota_holder() == Some(holder))
+						.count();
+					if held >= rules.quota {
+						over_quota.push(parcel);
+						continue;
+					}
+				}
+				let owner = parcel.owner.clone();
+				if let Some(displaced) = places.insert(key, parcel) {
+					report.add(displaced.owner, Some(displaced.location), ReportKind::LostConflict(owner));
+				}
+			}
+			waiting = over_quota;
+			if waiting.len() == count {
+				break;
 			}
 		}
+		for parcel in waiting {
+			report.add(parcel.owner, Some(parcel.location), ReportKind::QuotaExceeded(rules.quota));
+		}
+		let generation = old.generation + 1;
+		let mut history = old.history.clone();
+		let mut completed: Vec<(&PosKey, &&Transfer)> = transfers.iter()
+			.filter(|(key, transfer)| places.get(key).is_some_and(|parcel| transfer.is_claimed_by(parcel)))
+			.collect();
+		completed.sort_by_key(|(key, _)| (key.0.y, key.0.x));
+		for (key, transfer) in completed {
+			report.add(Owner::user(&transfer.to), Some(key.0), ReportKind::Transferred(transfer.from.clone()));
+			history.push(HistoryEvent::Transfer {
+				generation,
+				from: transfer.from.clone(),
+				to: transfer.to.clone(),
+				location: key.0
+			});
+		}
 		Self {
 			places,
-			background: old.background.next()
+			background: old.background.next(),
+			generation,
+			history,
+			charset: rules.charset.clone()
 		}
 	}
 
-	fn parcel(&self, pos: Pos) -> Option<&Parcel> {
+	/// The characters that were allowed in the parcels when the town was built
+	pub fn charset(&self) -> &Charset {
+		&self.charset
+	}
+
+	/// The parcel that occupies the plot at the given plot coordinates
+	pub fn parcel(&self, pos: Pos) -> Option<&Parcel> {
 		self.places.get(&PosKey::from_pos(pos))
 	}
 
-	fn owner_of(&self, pos: Pos) -> Option<Owner> {
+	/// All parcels made by a user, including shared parcels that they made
+	pub fn parcels_by<'a>(&'a self, user: &'a str) -> impl Iterator<Item=&'a Parcel> {
+		self.places.values().filter(move |parcel| parcel.owner.quota_holder() == Some(user))
+	}
+
+	/// The owner of the plot at the given plot coordinates
+	pub fn owner_of(&self, pos: Pos) -> Option<Owner> {
 		self.parcel(pos).map(|parcel| parcel.owner.clone())
 	}
 
-	pub fn render_text<F>(&self, width: usize, height: usize, mut writer: F) //-> impl Iterator<Item = String> + use<'_>{
+	/// All parcels, ordered by location
+	pub fn parcels(&self) -> Vec<&Parcel> {
+		self.find(|_| true)
+	}
+
+	/// All parcels that a user owns or co-owns, ordered by location
+	pub fn find_by_owner(&self, user: &str) -> Vec<&Parcel> {
+		self.find(|parcel| parcel.owner.users().contains(&user))
+	}
+
+	/// All parcels whose title, description or tags contain the text, ignoring case
+	pub fn search(&self, text: &str) -> Vec<&Parcel> {
+		self.find(|parcel| parcel.matches_text(text))
+	}
+
+	/// All parcels with the tag, ignoring case
+	pub fn find_by_tag(&self, tag: &str) -> Vec<&Parcel> {
+		self.find(|parcel| parcel.has_tag(tag))
+	}
+
+	/// All parcels matching the predicate, ordered by position
+	fn find(&self, predicate: impl Fn(&Parcel) -> bool) -> Vec<&Parcel> {
+		let mut parcels: Vec<&Parcel> = self.places.values()
+			.filter(|parcel| predicate(parcel))
+			.collect();
+		parcels.sort_by_key(|parcel| (parcel.location.y, parcel.location.x));
+		parcels
+	}
+
+	/// The claimed plots around a position, including diagonal neighbours, ordered by location
+	pub fn neighbours(&self, pos: Pos) -> Vec<&Parcel> {
+		(-1..=1)
+			.flat_map(|dy| (-1..=1).map(move |dx| pos + (dx, dy)))
+			.filter(|neighbour| *neighbour != pos)
+			.filter_map(|neighbour| self.parcel(neighbour))
+			.collect()
+	}
+
+	/// All unclaimed plots in the rectangle between two corners (inclusive), ordered by location
+	pub fn free_plots(&self, from: Pos, to: Pos) -> Vec<Pos> {
+		(from.y.min(to.y)..=from.y.max(to.y))
+			.flat_map(|y| (from.x.min(to.x)..=from.x.max(to.x)).map(move |x| Pos::new(x, y)))
+			.filter(|pos| self.parcel(*pos).is_none())
+			.collect()
+	}
+
+	/// The unclaimed plot in the rendered part of the town that is closest to a position
+	pub fn nearest_free(&self, pos: Pos) -> Option<Pos> {
+		self.free_plots(Pos::new(0, 0), Pos::new(TOWN_WIDTH as i64 - 1, TOWN_HEIGHT as i64 - 1))
+			.into_iter()
+			.min_by_key(|free| (free.distance_to(pos), free.y, free.x))
+	}
+
+	/// Render the plots from (0, 0) to (width, height) as plain text, passing each piece of text to the writer
+	pub fn render_text<F>(&self, width: usize, height: usize, writer: F) //-> impl Iterator<Item = String> + use<'_>{
 			where F: FnMut(&str) {
-		for y in 0..(height * PLOT_HEIGHT) {
-			let plot_y = y as i64 / PLOT_HEIGHT as i64;
-			let inner_y = y as usize % PLOT_HEIGHT;
-			for plot_x in 0..width {
-				if let Some(parcel) = self.parcel(Pos::new(plot_x as i64, plot_y)) {
-					writer(parcel.text_line(inner_y));
-				} else {
-					for x in (PLOT_WIDTH*plot_x)..(PLOT_WIDTH*(plot_x+1)) {
-						writer(self.background.char_at(Pos::new(x as i64, y as i64)));
-					}
+		self.render_text_area(Pos::zero(), width, height, writer)
+	}
+
+	/// Render width by height plots as plain text, starting with the plot at the given corner
+	pub fn render_text_area<F>(&self, corner: Pos, width: usize, height: usize, mut writer: F)
+			where F: FnMut(&str) {
+		for plot_y in corner.y..(corner.y + height as i64) {
+			for inner_y in 0..PLOT_HEIGHT {
+				for plot_x in corner.x..(corner.x + width as i64) {
+					self.write_plot_text_line(Pos::new(plot_x, plot_y), inner_y, &mut writer);
 				}
+				writer("\n")
+			}
+		}
+	}
+
+	/// A single line of a single plot as plain text: the art of the parcel there, or the background if it is unclaimed
+	pub fn plot_text_line(&self, plot: Pos, inner_y: usize) -> String {
+		let mut line = String::with_capacity(PLOT_WIDTH);
+		self.write_plot_text_line(plot, inner_y, &mut |txt: &str| line.push_str(txt));
+		line
+	}
+
+	fn write_plot_text_line<F>(&self, plot: Pos, inner_y: usize, writer: &mut F)
+			where F: FnMut(&str) {
+		if let Some(parcel) = self.parcel(plot) {
+			writer(parcel.text_line(inner_y));
+		} else {
+			let y = plot.y * PLOT_HEIGHT as i64 + inner_y as i64;
+			for x in (plot.x * PLOT_WIDTH as i64)..((plot.x + 1) * PLOT_WIDTH as i64) {
+				writer(self.background.char_at(Pos::new(x, y)));
+			}
+		}
+	}
+
+	/// A copy of this cadastre with the parcel placed at its location, replacing anything that was there
+	/// This ignores all rules, and is meant for previews
+	pub fn with_parcel(&self, parcel: Parcel) -> Self {
+		let mut cadastre = self.clone();
+		cadastre.places.insert(PosKey::from_pos(parcel.location), parcel);
+		cadastre
+	}
+
+	/// Render the plots from (0, 0) to (width, height) as an html page, passing each piece of html to the writer
+	pub fn render_html<F>(&self, width: usize, height: usize, writer: F) //-> impl Iterator<Item = String> + use<'_>{
+			where F: FnMut(&str) {
+		self.render_html_with(width, height, &HtmlOptions::default(), writer);
+	}
+
+	/// Render the town as html, with settings that don't come from the town itself
+	/// The page is made by filling in the placeholders of the template:
+	/// {{map}} for the town itself, {{title}}, {{style}} for the stylesheet and {{generated}} for the time of the render
+	/// Anything else between double braces is kept as it is
+	pub fn render_html_with<F>(&self, width: usize, height: usize, options: &HtmlOptions, mut writer: F)
+			where F: FnMut(&str) {
+		let mut rest = options.template.as_deref().unwrap_or(DEFAULT_HTML_TEMPLATE);
+		while let Some(start) = rest.find("{{") {
+			writer(&rest[..start]);
+			let Some((name, after)) = rest[start+2..].split_once("}}") else {
+				rest = &rest[start..];
+				break;
+			};
+			match name {
+				"map" => self.render_html_map(width, height, options, &mut writer),
+				"title" => writer(&escape_html(&options.title)),
+				"style" => writer(&html_style(options, self.has_inactive(width, height))),
+				"generated" => writer(&options.generated.map(format_timestamp).unwrap_or_default()),
+				_ => writer(&rest[start..start+name.len()+4])
 			}
-			writer("\n")
+			rest = after;
 		}
+		writer(rest);
 	}
 
-	pub fn render_html<F>(&self, width: usize, height: usize, mut writer: F) //-> impl Iterator<Item = String> + use<'_>{
+	/// The plot list and the art of the town
+	fn render_html_map<F>(&self, width: usize, height: usize, options: &HtmlOptions, writer: &mut F)
 			where F: FnMut(&str) {
-		writer("<!DOCTYPE html>\n<html>\n<!-- See tilde.town/~troido/cadastre for instructions -->\n<head>\n<meta charset='utf-8'>\n<style>\na {text-decoration: none}\n</style>\n</head>\n<body><pre>\n");
+		if options.accessible {
+			self.render_plot_list(width, height, writer);
+		}
+		if options.interactive {
+			self.render_plot_index(width, height, writer);
+		}
+		let anchors = self.user_anchors(width, height);
+		writer("<pre>\n");
 		for y in 0..(height * PLOT_HEIGHT) {
 			let plot_y = y as i64 / PLOT_HEIGHT as i64;
-			let inner_y = y as usize % PLOT_HEIGHT;
+			let inner_y = y % PLOT_HEIGHT;
 			for plot_x in 0..width {
 				if inner_y == 0 {
 					writer(&format!("<span id=\"{},{}\"></span>", plot_x, plot_y));
 				}
-				if let Some(parcel) = self.parcel(Pos::new(plot_x as i64, plot_y)) {
-					writer(&parcel.html_line(inner_y));
+				let pos = Pos::new(plot_x as i64, plot_y);
+				if let Some(parcel) = self.parcel(pos) {
+					writer(&parcel.html_line_with(inner_y, &HtmlLineOptions {
+						broken_links: &options.broken_links,
+						user_anchor: anchors.contains(&pos),
+						accessible: options.accessible
+					}));
 				} else {
+					// the background of unclaimed plots is decoration as well
+					if options.accessible {
+						writer("<span aria-hidden=\"true\">");
+					}
 					for x in (PLOT_WIDTH*plot_x)..(PLOT_WIDTH*(plot_x+1)) {
 						writer(self.background.char_at(Pos::new(x as i64, y as i64)));
 					}
+					if options.accessible {
+						writer("</span>");
+					}
 				}
 			}
 			writer("\n");
 		}
-		writer("</pre></body>\n<!-- Cadastre made by ~troido; art by tilde.town users -->\n</html>\n");
+		writer("</pre>");
+		if options.interactive {
+			self.render_script(width, height, writer);
+		}
+	}
+
+	/// Whether any of the rendered plots is inactive, so the page needs the style for it
+	fn has_inactive(&self, width: usize, height: usize) -> bool {
+		self.places.values().any(|parcel| {
+			parcel.inactive
+				&& (0..width as i64).contains(&parcel.location.x)
+				&& (0..height as i64).contains(&parcel.location.y)
+		})
+	}
+
+	/// The rendered plots that get the anchor of the user whose parcel file it is
+	/// Every user gets one anchor: on their main parcel, or else on the first of their additional parcels in reading order
+	fn user_anchors(&self, width: usize, height: usize) -> HashSet<Pos> {
+		let mut anchors: HashMap<&str, &Parcel> = HashMap::new();
+		for plot_y in 0..height {
+			for plot_x in 0..width {
+				let Some(parcel) = self.parcel(Pos::new(plot_x as i64, plot_y as i64)) else {
+					continue;
+				};
+				let Some(holder) = parcel.owner.quota_holder() else {
+					continue;
+				};
+				if anchors.get(holder).is_none_or(|anchor| anchor.name.is_some() && parcel.name.is_none()) {
+					anchors.insert(holder, parcel);
+				}
+			}
+		}
+		anchors.values().map(|parcel| parcel.location).collect()
+	}
+
+	/// A list of the plots by owner, that stays at the side of the page to quickly go to a plot
+	fn render_plot_index<F>(&self, width: usize, height: usize, writer: &mut F)
+			where F: FnMut(&str) {
+		let mut entries: Vec<(String, Pos)> = Vec::new();
+		for plot_y in 0..height {
+			for plot_x in 0..width {
+				let pos = Pos::new(plot_x as i64, plot_y as i64);
+				let Some(parcel) = self.parcel(pos) else {
+					continue;
+				};
+				let mut label: Vec<String> = parcel.owner.users().iter().map(|name| format!("~{}", name)).collect();
+				label.extend(parcel.title.clone());
+				let label = if label.is_empty() { parcel.alt_text() } else { label.join(" ") };
+				entries.push((label, pos));
+			}
+		}
+		entries.sort_by_key(|(label, pos)| (label.to_lowercase(), pos.y, pos.x));
+		writer("<details class=\"plotindex\">\n<summary>Plots</summary>\n<ul>\n");
+		for (label, pos) in entries {
+			writer(&format!("<li><a href=\"#{},{}\">{}</a> {},{}</li>\n", pos.x, pos.y, escape_html(&label), pos.x, pos.y));
+		}
+		writer("</ul>\n</details>\n");
+	}
+
+	/// The owners and titles of the plots as json, and the script that uses them for the hover information, minimap and zoom
+	fn render_script<F>(&self, width: usize, height: usize, writer: &mut F)
+			where F: FnMut(&str) {
+		let mut plots = Vec::new();
+		for plot_y in 0..height {
+			for plot_x in 0..width {
+				if let Some(parcel) = self.parcel(Pos::new(plot_x as i64, plot_y as i64)) {
+					plots.push(serde_json::json!({"x": plot_x, "y": plot_y, "owners": parcel.owner.users(), "title": parcel.title}));
+				}
+			}
+		}
+		let data = serde_json::json!({"width": width, "height": height, "plotWidth": PLOT_WIDTH, "plotHeight": PLOT_HEIGHT, "plots": plots});
+		// '<' can only occur inside json strings, where it can be escaped so the text can't close the script element
+		writer(&format!("\n<script type=\"application/json\" id=\"cadastre-data\">{}</script>\n", data.to_string().replace('<', "\\u003c")));
+		writer(&format!("<script>\n{}</script>\n", INTERACTIVE_SCRIPT));
+	}
+
+	/// A list of the plots in reading order with their alt text and links, for screen readers and keyboard users
+	/// It is hidden visually until one of its links gets focus, since the art already shows the same
+	fn render_plot_list<F>(&self, width: usize, height: usize, writer: &mut F)
+			where F: FnMut(&str) {
+		writer("<nav class=\"plotlist\" aria-label=\"Plots\">\n<ul>\n");
+		for plot_y in 0..height {
+			for plot_x in 0..width {
+				let Some(parcel) = self.parcel(Pos::new(plot_x as i64, plot_y as i64)) else {
+					continue;
+				};
+				writer(&format!("<li><a href=\"#{},{}\">{}</a>", plot_x, plot_y, escape_html(&parcel.alt_text())));
+				let links = parcel.links_in_order();
+				if !links.is_empty() {
+					writer("<ul>");
+					for (_key, link) in links {
+						writer(&format!("<li><a href=\"{}\">{}</a></li>", link.url.replace('"', "&quot;"), escape_html(link.name())));
+					}
+					writer("</ul>");
+				}
+				writer("</li>\n");
+			}
+		}
+		writer("</ul>\n</nav>\n");
+	}
+}
+
+/// The page that the town is rendered in when no other template is given
+pub const DEFAULT_HTML_TEMPLATE: &str = "<!DOCTYPE html>\n<html>\n<!-- See tilde.town/~troido/cadastre for instructions -->\n<head>\n<meta charset='utf-8'>\n<style>\n{{style}}</style>\n</head>\n<body>{{map}}</body>\n<!-- Cadastre made by ~troido; art by tilde.town users -->\n</html>\n";
+
+/// The script for the interactive html render
+const INTERACTIVE_SCRIPT: &str = include_str!("interactive.js");
+
+/// Settings for the html render that don't come from the town
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlOptions {
+	/// Urls that the link checker found to be broken; links to them get the 'broken' class
+	pub broken_links: HashSet<String>,
+	/// The page to render the town in; the default template if this is None
+	pub template: Option<String>,
+	/// The colours of the page, added to the stylesheet
+	pub theme: HtmlTheme,
+	/// The title for templates that have a {{title}} placeholder
+	pub title: String,
+	/// The time of the render for templates that have a {{generated}} placeholder
+	pub generated: Option<SystemTime>,
+	/// Add a plot index and a script for hover information, a minimap and zooming
+	/// Without javascript, the page still works like the plain one with the index added
+	pub interactive: bool,
+	/// Label the plots with their alt text for screen readers, name the links, and add a list of the plots with their links
+	pub accessible: bool
+}
+
+impl Default for HtmlOptions {
+	fn default() -> Self {
+		Self {
+			broken_links: HashSet::new(),
+			template: None,
+			theme: HtmlTheme::Plain,
+			title: "Cadastre".to_string(),
+			generated: None,
+			interactive: false,
+			accessible: false
+		}
+	}
+}
+
+/// The stylesheet for the {{style}} placeholder
+/// Rules are only added when the page uses them, so the default page stays as it always was
+fn html_style(options: &HtmlOptions, has_inactive: bool) -> String {
+	let mut style = "a {text-decoration: none}\n".to_string();
+	if has_inactive {
+		style.push_str(".inactive {opacity: 0.5}\n");
+	}
+	if options.accessible {
+		style.push_str(concat!(
+			".plotlist:not(:focus-within) {position: absolute; width: 1px; height: 1px; overflow: hidden; clip: rect(0 0 0 0); white-space: nowrap}\n",
+			".plotlist:focus-within {position: fixed; top: 0.5em; left: 0.5em; z-index: 1; max-height: 60vh; overflow: auto; padding: 0.25em 0.5em; border: 1px solid; background-color: Canvas; color: CanvasText}\n"
+		));
+	}
+	if !options.broken_links.is_empty() {
+		style.push_str("a.broken {text-decoration: line-through}\n");
+	}
+	if options.interactive {
+		style.push_str(concat!(
+			"html {scroll-behavior: smooth}\n",
+			".plotindex {position: fixed; top: 0.5em; right: 0.5em; max-height: 60vh; overflow: auto; padding: 0.25em 0.5em; border: 1px solid; background-color: Canvas; color: CanvasText}\n",
+			".plotindex ul {padding-left: 1em}\n",
+			".mapcontrols {position: fixed; bottom: 0.5em; right: 0.5em; padding: 0.25em; border: 1px solid; background-color: Canvas; color: CanvasText}\n",
+			".mapcontrols canvas {cursor: pointer}\n",
+			".hoverinfo {position: fixed; pointer-events: none; white-space: pre; padding: 0.1em 0.3em; border: 1px solid; background-color: Canvas; color: CanvasText}\n"
+		));
+	}
+	style.push_str(match options.theme {
+		HtmlTheme::Plain => "",
+		HtmlTheme::Light => "body, .plotlist, .plotindex, .mapcontrols, .hoverinfo {background-color: #fbfaf5; color: #222}\na {color: #1a5fb4}\na.broken {color: #a51d2d}\n",
+		HtmlTheme::Dark => "body, .plotlist, .plotindex, .mapcontrols, .hoverinfo {background-color: #1d1f21; color: #d8d8d8}\na {color: #81a2eb}\na.broken {color: #e06c75}\n"
+	});
+	style
+}
+
+/// A time as UTC date and time, like '2024-03-01 13:37 UTC'
+fn format_timestamp(time: SystemTime) -> String {
+	let secs = time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+	let (days, secs_of_day) = (secs / 86400, secs % 86400);
+	// convert days since 1970-01-01 to a civil date, with years starting in March so leap days come last
+	let days = days as i64 + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days.rem_euclid(146097);
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month_index = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+	let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+	format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, secs_of_day / 3600, secs_of_day % 3600 / 60)
+}
+
+/// The colours of the html render
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum HtmlTheme {
+	/// the default colours of the browser
+	Plain,
+	/// dark text on a light background
+	Light,
+	/// light text on a dark background
+	Dark
+}
+
+/// Policies that decide which parcels are accepted when building a new cadastre
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildRules {
+	/// The maximum number of plots that a single user can hold
+	pub quota: usize,
+	/// Plots that users want to give to another user
+	pub transfers: Vec<Transfer>,
+	/// The characters that were allowed when parsing the parcels, to be recorded in the town
+	pub charset: Charset
+}
+
+impl Default for BuildRules {
+	fn default() -> Self {
+		Self { quota: 1, transfers: Vec::new(), charset: Charset::default() }
+	}
+}
+
+/// Notable changes to the cadastre that are kept in the town json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag="event", rename_all="lowercase")]
+pub enum HistoryEvent {
+	Transfer {
+		generation: u64,
+		from: String,
+		to: String,
+		location: Pos
 	}
 }
 
@@ -138,6 +612,9 @@ impl<'de> Deserialize<'de> for PosKey {
 mod tests {
 	use super::*;
 	use crate::hashmap;
+	use crate::link::Link;
+	use std::time::Duration;
+	use crate::report::ReportEntry;
 
 	#[test]
 	fn serialize_poskey_to_and_from_string() {
@@ -151,7 +628,7 @@ mod tests {
 			Parcel::empty(Owner::user("odiort"), Pos::new(3, 2)),
 			Parcel::empty(Owner::Public, Pos::new(3, 3)),
 			Parcel::empty(Owner::Admin, Pos::new(2, 2)),
-		].into_iter())
+		].into_iter(), &BuildRules::default(), &mut Report::new())
 	}
 
 	#[test]
@@ -160,7 +637,7 @@ mod tests {
 			Parcel::empty(Owner::user("troido"), Pos::new(3, 2)),
 			Parcel::empty(Owner::user("odiort"), Pos::new(2, 3)),
 			Parcel::empty(Owner::Public, Pos::new(2, 2)),
-		].into_iter());
+		].into_iter(), &BuildRules::default(), &mut Report::new());
 		assert_eq!(cadastre.owner_of(Pos::new(3, 2)), Some(Owner::user("troido")));
 		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("odiort")));
 		assert_eq!(cadastre.owner_of(Pos::new(2, 2)), Some(Owner::Public));
@@ -175,7 +652,7 @@ mod tests {
 			Parcel::empty(Owner::user("odiort"), Pos::new(3, 2)),
 			Parcel::empty(Owner::user("joe"), Pos::new(2, 3)),
 			Parcel::empty(Owner::user("josh"), Pos::new(3, 2)),
-		].into_iter());
+		].into_iter(), &BuildRules::default(), &mut Report::new());
 		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("troido")));
 		assert_eq!(cadastre.owner_of(Pos::new(3, 2)), Some(Owner::user("odiort")));
 	}
@@ -190,11 +667,214 @@ mod tests {
 			Parcel::empty(Owner::Admin, Pos::new(2, 3)),
 			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
 			Parcel::empty(Owner::Public, Pos::new(3, 3)),
-		].into_iter());
+		].into_iter(), &BuildRules::default(), &mut Report::new());
 		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::Admin));
 		assert_eq!(cadastre.owner_of(Pos::new(3, 3)), Some(Owner::user("odiort")));
 	}
 
+	#[test]
+	fn quota_limits_plots_per_user() {
+		let mut report = Report::new();
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("troido"), Pos::new(5, 5)),
+			Parcel::empty(Owner::Public, Pos::new(6, 6)),
+			Parcel::empty(Owner::Public, Pos::new(7, 7)),
+		].into_iter(), &BuildRules::default(), &mut report);
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("troido")));
+		assert_eq!(cadastre.owner_of(Pos::new(5, 5)), None);
+		assert_eq!(cadastre.owner_of(Pos::new(6, 6)), Some(Owner::Public));
+		assert_eq!(cadastre.owner_of(Pos::new(7, 7)), Some(Owner::Public));
+		assert_eq!(report.entries, vec![
+			ReportEntry { owner: Owner::user("troido"), location: Some(Pos::new(5, 5)), kind: ReportKind::QuotaExceeded(1) }
+		]);
+
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("troido"), Pos::new(5, 5)),
+			Parcel::empty(Owner::user("troido"), Pos::new(6, 6)),
+		].into_iter(), &BuildRules { quota: 2, ..BuildRules::default() }, &mut Report::new());
+		assert_eq!(cadastre.owner_of(Pos::new(5, 5)), Some(Owner::user("troido")));
+		assert_eq!(cadastre.owner_of(Pos::new(6, 6)), None);
+	}
+
+	#[test]
+	fn displaced_plots_free_up_quota() {
+		let mut report = Report::new();
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("troido"), Pos::new(5, 5)),
+			Parcel::empty(Owner::Admin, Pos::new(2, 3)),
+		].into_iter(), &BuildRules::default(), &mut report);
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::Admin));
+		assert_eq!(cadastre.owner_of(Pos::new(5, 5)), Some(Owner::user("troido")));
+		assert_eq!(report.entries, vec![
+			ReportEntry { owner: Owner::user("troido"), location: Some(Pos::new(2, 3)), kind: ReportKind::LostConflict(Owner::Admin) }
+		]);
+	}
+
+	#[test]
+	fn held_plots_count_first_for_quota() {
+		let mut report = Report::new();
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("troido"), Pos::new(5, 5)),
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+		].into_iter(), &BuildRules::default(), &mut report);
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("troido")));
+		assert_eq!(cadastre.owner_of(Pos::new(5, 5)), None);
+		assert_eq!(report.entries, vec![
+			ReportEntry { owner: Owner::user("troido"), location: Some(Pos::new(5, 5)), kind: ReportKind::QuotaExceeded(1) }
+		]);
+	}
+
+	fn named(owner: Owner, location: Pos, name: &str) -> Parcel {
+		Parcel { name: Some(name.to_string()), ..Parcel::empty(owner, location) }
+	}
+
+	#[test]
+	fn tenancy_is_tracked_per_named_parcel() {
+		let rules = BuildRules { quota: 2, ..BuildRules::default() };
+		let old = Cadastre::build(&Cadastre::empty(), vec![
+			named(Owner::user("troido"), Pos::new(4, 4), "house"),
+			named(Owner::user("troido"), Pos::new(5, 4), "garden"),
+		].into_iter(), &rules, &mut Report::new());
+
+		let cadastre = Cadastre::build(&old, vec![
+			Parcel::empty(Owner::user("odiort"), Pos::new(4, 4)),
+			named(Owner::user("troido"), Pos::new(4, 4), "garden"),
+			named(Owner::user("troido"), Pos::new(4, 4), "house"),
+		].into_iter(), &rules, &mut Report::new());
+		assert_eq!(cadastre.parcel(Pos::new(4, 4)).unwrap().name, Some("house".to_string()));
+
+		let cadastre = Cadastre::build(&old, vec![
+			Parcel::empty(Owner::user("odiort"), Pos::new(4, 4)),
+			named(Owner::user("troido"), Pos::new(4, 4), "garden"),
+		].into_iter(), &rules, &mut Report::new());
+		assert_eq!(cadastre.owner_of(Pos::new(4, 4)), Some(Owner::user("odiort")));
+	}
+
+	#[test]
+	fn transfer_moves_tenancy_to_recipient() {
+		let rules = BuildRules {
+			transfers: vec![Transfer { from: "troido".to_string(), to: "john".to_string(), location: None }],
+			..BuildRules::default()
+		};
+		let mut report = Report::new();
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("joe"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("john"), Pos::new(2, 3)),
+		].into_iter(), &rules, &mut report);
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("john")));
+		assert_eq!(cadastre.history, vec![
+			HistoryEvent::Transfer { generation: 2, from: "troido".to_string(), to: "john".to_string(), location: Pos::new(2, 3) }
+		]);
+		assert!(report.entries.contains(
+			&ReportEntry { owner: Owner::user("john"), location: Some(Pos::new(2, 3)), kind: ReportKind::Transferred("troido".to_string()) }
+		));
+
+		// in later updates the recipient keeps the tenancy
+		let cadastre = Cadastre::build(&cadastre, vec![
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("john"), Pos::new(2, 3)),
+		].into_iter(), &BuildRules::default(), &mut Report::new());
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("john")));
+		assert_eq!(cadastre.history.len(), 1);
+	}
+
+	#[test]
+	fn transfer_waits_for_recipient() {
+		let rules = BuildRules {
+			transfers: vec![Transfer { from: "troido".to_string(), to: "john".to_string(), location: Some(Pos::new(2, 3)) }],
+			..BuildRules::default()
+		};
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("joe"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("john"), Pos::new(5, 5)),
+		].into_iter(), &rules, &mut Report::new());
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("troido")));
+		assert!(cadastre.history.is_empty());
+	}
+
+	#[test]
+	fn giver_keeps_tenancy_until_recipient_claims() {
+		let rules = BuildRules {
+			transfers: vec![Transfer { from: "troido".to_string(), to: "john".to_string(), location: None }],
+			..BuildRules::default()
+		};
+		// john already holds a plot, so his claim fails on the quota and troido keeps the plot
+		let old = some_cadastre().with_parcel(Parcel::empty(Owner::user("john"), Pos::new(5, 5)));
+		let cadastre = Cadastre::build(&old, vec![
+			Parcel::empty(Owner::user("john"), Pos::new(5, 5)),
+			Parcel::empty(Owner::user("joe"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("john"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+		].into_iter(), &rules, &mut Report::new());
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("troido")));
+		assert!(cadastre.history.is_empty());
+	}
+
+	#[test]
+	fn transfer_with_location_takes_precedence() {
+		let rules = BuildRules {
+			transfers: vec![
+				Transfer { from: "troido".to_string(), to: "john".to_string(), location: None },
+				Transfer { from: "troido".to_string(), to: "joe".to_string(), location: Some(Pos::new(2, 3)) },
+			],
+			..BuildRules::default()
+		};
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("joe"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("john"), Pos::new(2, 3)),
+		].into_iter(), &rules, &mut Report::new());
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("joe")));
+	}
+
+	#[test]
+	fn active_users_can_claim_plots_of_inactive_users() {
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel { inactive: true, ..Parcel::empty(Owner::user("troido"), Pos::new(2, 3)) },
+			Parcel::empty(Owner::user("john"), Pos::new(2, 3)),
+			Parcel { inactive: true, ..Parcel::empty(Owner::user("odiort"), Pos::new(3, 3)) },
+			Parcel::empty(Owner::Public, Pos::new(3, 3)),
+		].into_iter(), &BuildRules::default(), &mut Report::new());
+		assert_eq!(cadastre.owner_of(Pos::new(2, 3)), Some(Owner::user("john")));
+		assert_eq!(cadastre.owner_of(Pos::new(3, 3)), Some(Owner::user("odiort")));
+	}
+
+	#[test]
+	fn lost_conflicts_are_reported() {
+		let mut report = Report::new();
+		Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("john"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+			Parcel::empty(Owner::user("joe"), Pos::new(2, 3)),
+		].into_iter(), &BuildRules::default(), &mut report);
+		assert_eq!(report.entries, vec![
+			ReportEntry { owner: Owner::user("john"), location: Some(Pos::new(2, 3)), kind: ReportKind::LostConflict(Owner::user("troido")) },
+			ReportEntry { owner: Owner::user("joe"), location: Some(Pos::new(2, 3)), kind: ReportKind::LostConflict(Owner::user("troido")) },
+		]);
+	}
+
+	#[test]
+	fn query_plots() {
+		let cadastre = Cadastre::build(&some_cadastre(), vec![
+			Parcel::empty(Owner::user("troido"), Pos::new(2, 3)),
+			Parcel::empty(Owner::Shared(vec!["odiort".to_string(), "troido".to_string()]), Pos::new(3, 2)),
+			Parcel::empty(Owner::Public, Pos::new(3, 3)),
+			Parcel::empty(Owner::Admin, Pos::new(0, 0)),
+		].into_iter(), &BuildRules::default(), &mut Report::new());
+		let locations = |parcels: Vec<&Parcel>| parcels.iter().map(|parcel| parcel.location).collect::<Vec<Pos>>();
+		assert_eq!(locations(cadastre.find_by_owner("troido")), vec![Pos::new(3, 2), Pos::new(2, 3)]);
+		assert_eq!(locations(cadastre.find_by_owner("odiort")), vec![Pos::new(3, 2)]);
+		assert_eq!(locations(cadastre.find_by_owner("john")), vec![]);
+		assert_eq!(locations(cadastre.neighbours(Pos::new(2, 2))), vec![Pos::new(3, 2), Pos::new(2, 3), Pos::new(3, 3)]);
+		assert_eq!(cadastre.free_plots(Pos::new(3, 3), Pos::new(2, 2)), vec![Pos::new(2, 2)]);
+		assert_eq!(cadastre.nearest_free(Pos::new(3, 3)), Some(Pos::new(4, 3)));
+		assert_eq!(cadastre.nearest_free(Pos::new(-5, 0)), Some(Pos::new(1, 0)));
+	}
+
 	#[test]
 	fn render_text() {
 		let mut text = String::new();
@@ -229,6 +909,87 @@ mod tests {
 		compare_text(&text, expected);
 	}
 
+	#[test]
+	fn record_charset() {
+		let rules = BuildRules { charset: Charset::parse("a..f").unwrap(), ..BuildRules::default() };
+		let town = Cadastre::build(&Cadastre::empty(), std::iter::empty(), &rules, &mut Report::new());
+		assert_eq!(town.charset(), &rules.charset);
+		let json = serde_json::to_string(&town).unwrap();
+		assert_eq!(serde_json::from_str::<Cadastre>(&json).unwrap().charset(), &rules.charset);
+		let old_json = json.replace(",\"charset\":\" ?abcdef\"", "");
+		assert_ne!(old_json, json);
+		assert_eq!(serde_json::from_str::<Cadastre>(&old_json).unwrap().charset(), &Charset::default());
+	}
+
+	#[test]
+	fn render_text_area_around_parcel() {
+		let mut text = String::new();
+		let parcel = Parcel::from_text("1 0\nhello", Owner::user("john")).unwrap();
+		let cadastre = little_town().with_parcel(parcel);
+		cadastre.render_text_area(Pos::new(1, 0), 1, 2, |line| text.push_str(line));
+		assert_eq!(text.lines().next(), Some("hello                   "));
+		assert_eq!(text.lines().nth(12), Some("╔══════════════════════╗"));
+		assert_eq!(cadastre.plot_text_line(Pos::new(1, 1), 11), "╚══════════════════════╝");
+		assert_eq!(cadastre.plot_text_line(Pos::new(-1, -1), 0), little_town().plot_text_line(Pos::new(-1, -1), 0));
+		assert_eq!(little_town().owner_of(Pos::new(1, 0)), Some(Owner::Public));
+	}
+
+	#[test]
+	fn render_html_template() {
+		let options = HtmlOptions {
+			template: Some("<title>{{title}}</title><style>{{style}}</style>{{ map }}{{generated}}{{map}}{{unclosed".to_string()),
+			theme: HtmlTheme::Dark,
+			title: "Town & co".to_string(),
+			generated: Some(UNIX_EPOCH + Duration::from_secs(951_782_400 + 13 * 3600 + 37 * 60)),
+			accessible: true,
+			..HtmlOptions::default()
+		};
+		let mut text = String::new();
+		let town = Cadastre::empty().with_parcel(Parcel { inactive: true, ..Parcel::empty(Owner::Public, Pos::new(1, 0)) });
+		town.render_html_with(2, 1, &options, |html| text.push_str(html));
+		assert!(text.starts_with("<title>Town &amp; co</title><style>a {text-decoration: none}\n.inactive {opacity: 0.5}\n"));
+		assert!(text.contains("body, .plotlist, .plotindex, .mapcontrols, .hoverinfo {background-color: #1d1f21; color: #d8d8d8}\n"));
+		assert!(text.contains("</style>{{ map }}2000-02-29 13:37 UTC<nav class=\"plotlist\""));
+		assert!(text.contains("<pre>\n<span id=\"0,0\"></span><span aria-hidden=\"true\">"));
+		assert!(text.ends_with("</pre>{{unclosed"));
+		assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01 00:00 UTC");
+		assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(1_798_761_599)), "2026-12-31 23:59 UTC");
+	}
+
+	#[test]
+	fn render_html_interactive() {
+		let mut parcel = Parcel::empty(Owner::user("troido"), Pos::new(1, 0));
+		parcel.title = Some("</script><b>".to_string());
+		let town = little_town().with_parcel(parcel);
+		let options = HtmlOptions { interactive: true, ..HtmlOptions::default() };
+		let mut text = String::new();
+		town.render_html_with(2, 2, &options, |html| text.push_str(html));
+		assert!(text.contains("<details class=\"plotindex\">\n<summary>Plots</summary>\n<ul>\n<li><a href=\"#1,1\">Plot at 1,1</a> 1,1</li>\n<li><a href=\"#0,1\">~troido</a> 0,1</li>\n<li><a href=\"#1,0\">~troido &lt;/script&gt;&lt;b&gt;</a> 1,0</li>\n<li><a href=\"#0,0\">~vilmibm</a> 0,0</li>\n</ul>\n</details>\n<pre>\n"));
+		let data_start = text.find("id=\"cadastre-data\">").unwrap() + 19;
+		let data_end = data_start + text[data_start..].find("</script>").unwrap();
+		let data: serde_json::Value = serde_json::from_str(&text[data_start..data_end]).unwrap();
+		assert_eq!(data["plots"][1], serde_json::json!({"x": 1, "y": 0, "owners": ["troido"], "title": "</script><b>"}));
+		assert_eq!(data["width"], 2);
+		assert!(text.contains(INTERACTIVE_SCRIPT));
+		let mut plain = String::new();
+		town.render_html(2, 2, |html| plain.push_str(html));
+		assert!(!plain.contains("<script"));
+	}
+
+	#[test]
+	fn one_anchor_per_user() {
+		let town = Cadastre::empty()
+			.with_parcel(named(Owner::user("troido"), Pos::new(0, 0), "garden"))
+			.with_parcel(Parcel::empty(Owner::user("troido"), Pos::new(1, 1)))
+			.with_parcel(named(Owner::user("vilmibm"), Pos::new(1, 0), "b"))
+			.with_parcel(named(Owner::user("vilmibm"), Pos::new(0, 1), "a"));
+		assert_eq!(town.user_anchors(2, 2), HashSet::from([Pos::new(1, 1), Pos::new(1, 0)]));
+		let mut text = String::new();
+		town.render_html(2, 2, |html| text.push_str(html));
+		assert_eq!(text.matches("id=\"troido\"").count(), 1);
+		assert_eq!(text.matches("id=\"vilmibm\"").count(), 1);
+	}
+
 	#[test]
 	fn render_html() {
 		let mut text = String::new();
@@ -276,9 +1037,70 @@ a {text-decoration: none}
 		compare_text(&text, expected);
 	}
 
+	#[test]
+	fn render_html_accessible() {
+		let mut text = String::new();
+		let cadastre = little_town();
+		let options = HtmlOptions { accessible: true, ..HtmlOptions::default() };
+		cadastre.render_html_with(2, 2, &options, |line| text.push_str(line));
+		// println!("{}", text);
+		let expected = r##"<!DOCTYPE html>
+<html>
+<!-- See tilde.town/~troido/cadastre for instructions -->
+<head>
+<meta charset='utf-8'>
+<style>
+a {text-decoration: none}
+.plotlist:not(:focus-within) {position: absolute; width: 1px; height: 1px; overflow: hidden; clip: rect(0 0 0 0); white-space: nowrap}
+.plotlist:focus-within {position: fixed; top: 0.5em; left: 0.5em; z-index: 1; max-height: 60vh; overflow: auto; padding: 0.25em 0.5em; border: 1px solid; background-color: Canvas; color: CanvasText}
+</style>
+</head>
+<body><nav class="plotlist" aria-label="Plots">
+<ul>
+<li><a href="#0,0">Plot of ~vilmibm at 0,0</a><ul><li><a href="https://tilde.town/~vilmibm">https://tilde.town/~vilmibm</a></li><li><a href="https://libraryofbabel.info/random.cgi">https://libraryofbabel.info/random.cgi</a></li></ul></li>
+<li><a href="#1,0">Plot at 1,0</a></li>
+<li><a href="#0,1">Plot of ~troido at 0,1</a><ul><li><a href="https://tilde.town/~troido/cadastre/">https://tilde.town/~troido/cadastre/</a></li><li><a href="https://tilde.town/~troido/entrance.html">https://tilde.town/~troido/entrance.html</a></li><li><a href="https://tilde.town/~troido/index.html">https://tilde.town/~troido/index.html</a></li></ul></li>
+<li><a href="#1,1">Plot at 1,1</a><ul><li><a href="https://tilde.town/~troido/cadastre">https://tilde.town/~troido/cadastre</a></li><li><a href="https://github.com/jmdejong/cadastre">https://github.com/jmdejong/cadastre</a></li><li><a href="https://tilde.town/~troido/index.html">https://tilde.town/~troido/index.html</a></li></ul></li>
+</ul>
+</nav>
+<pre>
+<span id="0,0"></span><span id="vilmibm"><span role="img" aria-label="Plot of ~vilmibm at 0,0">+------.................</span></span><span id="1,0"></span><span role="img" aria-label="Plot at 1,0">                       .</span>
+<span aria-hidden="true">|      |               .</span><span aria-hidden="true">                       .</span>
+<span aria-hidden="true"> . |      |           . </span><span aria-hidden="true">__                     .</span>
+<span aria-hidden="true">..|          |         .</span><span aria-hidden="true"> ~\________            .</span>
+<span aria-hidden="true">|              |       .</span><span aria-hidden="true">_   ~  ~   \_ {%%}     .</span>
+<span aria-hidden="true">|                 |   . </span><span aria-hidden="true"> \_______~&lt;&gt;&lt;{%%%%}    .</span>
+<span aria-hidden="true">|     </span><a href="https://tilde.town/~vilmibm" aria-label="https://tilde.town/~vilmibm">feels</a><span aria-hidden="true">         |.  </span><span aria-hidden="true">         \   ~{%%}     .</span>
+<span aria-hidden="true">|       </span><a href="https://tilde.town/~vilmibm" aria-label="https://tilde.town/~vilmibm">must</a><span aria-hidden="true">          | </span><span aria-hidden="true">          \&gt;&lt;&gt;!||      .</span>
+<span aria-hidden="true">|         </span><a href="https://tilde.town/~vilmibm" aria-label="https://tilde.town/~vilmibm">flow</a><span aria-hidden="true">         |</span><span aria-hidden="true">          |~  !||      .</span>
+<span aria-hidden="true">|         _            |</span><span aria-hidden="true">           \  ~ `\     .</span>
+<span aria-hidden="true">|      ---  -_         |</span><span aria-hidden="true">            \__   \    .</span>
+<span aria-hidden="true">+------ .......--------</span><a href="https://libraryofbabel.info/random.cgi" aria-label="https://libraryofbabel.info/random.cgi">π</a><span aria-hidden="true">               \~  |   .</span>
+<span id="0,1"></span><span id="troido"><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">+==</a><span role="img" aria-label="Plot of ~troido at 0,1">()</span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">=================+</a><span aria-hidden="true">.</span></span><span id="1,1"></span><span role="img" aria-label="Plot at 1,1">╔══════════════════════╗</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true"> (%&amp;8)  </span><a href="https://tilde.town/~troido/entrance.html" aria-label="https://tilde.town/~troido/entrance.html">/\</a><span aria-hidden="true">       _,__</span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║ Tilde.town Cadastre  ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">(&amp;(%)%)</span><a href="https://tilde.town/~troido/entrance.html" aria-label="https://tilde.town/~troido/entrance.html">/  \</a><span aria-hidden="true">    . __,_</span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║                      ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true"> (%8%)</span><a href="https://tilde.town/~troido/entrance.html" aria-label="https://tilde.town/~troido/entrance.html">/_##_\</a><span aria-hidden="true">   .     </span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║ Any tilde.town user  ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">  ||/ </span><a href="https://tilde.town/~troido/entrance.html" aria-label="https://tilde.town/~troido/entrance.html">|    |</a><span aria-hidden="true">   . @   </span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║ can claim a parcel   ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">  ||  </span><a href="https://tilde.town/~troido/entrance.html" aria-label="https://tilde.town/~troido/entrance.html">| /\ |</a><span aria-hidden="true"> * . @   </span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║ of land to show some ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">  ||  </span><a href="https://tilde.town/~troido/entrance.html" aria-label="https://tilde.town/~troido/entrance.html">|_||_|</a><span aria-hidden="true">   .     </span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║ awesome ascii art    ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">        ..  *  . "'` </span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║                      ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true"> (%) O  ........     </span><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║ * </span><a href="https://tilde.town/~troido/cadastre" aria-label="https://tilde.town/~troido/cadastre">Instructions</a><span aria-hidden="true">       ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">        ..    </span><a href="https://tilde.town/~troido/index.html" aria-label="https://tilde.town/~troido/index.html">~troido</a><a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">|</a><span aria-hidden="true">.</span><span aria-hidden="true">║ * </span><a href="https://github.com/jmdejong/cadastre" aria-label="https://github.com/jmdejong/cadastre">source (github)</a><span aria-hidden="true">    ║</span>
+<a href="https://tilde.town/~troido/cadastre/" aria-label="https://tilde.town/~troido/cadastre/">+=======#  #==========+</a><span aria-hidden="true">.</span><span aria-hidden="true">║      Made by </span><a href="https://tilde.town/~troido/index.html" aria-label="https://tilde.town/~troido/index.html">~troido</a><span aria-hidden="true"> ║</span>
+<span aria-hidden="true">........................</span><span aria-hidden="true">╚══════════════════════╝</span>
+</pre></body>
+<!-- Cadastre made by ~troido; art by tilde.town users -->
+</html>
+"##;
+		compare_text(&text, expected);
+	}
+
 	fn little_town() -> Cadastre {
 		Cadastre {
 			background: Background(8138474425133413201),
+			generation: 0,
+			history: Vec::new(),
+			charset: Charset::default(),
 			places: hashmap!(
 				PosKey::new(0, 0) => Parcel {
 					owner: Owner::user("vilmibm"),
@@ -312,9 +1134,16 @@ a {text-decoration: none}
 						"+------ .......--------2"
 					].map(String::from).to_vec(),
 					links: hashmap!(
-						'1' => "https://tilde.town/~vilmibm".to_string(),
-						'2' => "https://libraryofbabel.info/random.cgi".to_string()
-					)
+						'1' => Link::new("https://tilde.town/~vilmibm"),
+						'2' => Link::new("https://libraryofbabel.info/random.cgi")
+					),
+					title: None,
+					description: None,
+					tags: Vec::new(),
+					alt: None,
+					name: None,
+					inactive: false,
+					stale: 0
 				},
 				PosKey::new(0, 1) => Parcel {
 					owner: Owner::user("troido"),
@@ -348,10 +1177,17 @@ a {text-decoration: none}
 						"........................"
 					].map(String::from).to_vec(),
 					links: hashmap!(
-						'1' => "https://tilde.town/~troido/cadastre/".to_string(),
-						'2' => "https://tilde.town/~troido/index.html".to_string(),
-						'3' => "https://tilde.town/~troido/entrance.html".to_string()
-					)
+						'1' => Link::new("https://tilde.town/~troido/cadastre/"),
+						'2' => Link::new("https://tilde.town/~troido/index.html"),
+						'3' => Link::new("https://tilde.town/~troido/entrance.html")
+					),
+					title: None,
+					description: None,
+					tags: Vec::new(),
+					alt: None,
+					name: None,
+					inactive: false,
+					stale: 0
 				},
 				PosKey::new(1, 1) => Parcel {
 					owner: Owner::Admin,
@@ -385,10 +1221,17 @@ a {text-decoration: none}
 						"~~~~~~~~~~~~~~~~~~~~~~~~"
 					].map(String::from).to_vec(),
 					links: hashmap!(
-						'1' => "https://tilde.town/~troido/cadastre".to_string(),
-						'2' => "https://github.com/jmdejong/cadastre".to_string(),
-						'3' => "https://tilde.town/~troido/index.html".to_string()
-					)
+						'1' => Link::new("https://tilde.town/~troido/cadastre"),
+						'2' => Link::new("https://github.com/jmdejong/cadastre"),
+						'3' => Link::new("https://tilde.town/~troido/index.html")
+					),
+					title: None,
+					description: None,
+					tags: Vec::new(),
+					alt: None,
+					name: None,
+					inactive: false,
+					stale: 0
 				},
 				PosKey::new(1, 0) => Parcel {
 					owner: Owner::Public,
@@ -421,7 +1264,14 @@ a {text-decoration: none}
 						"                        ",
 						"                        "
 					].map(String::from).to_vec(),
-					links: HashMap::new()
+					links: HashMap::new(),
+					title: None,
+					description: None,
+					tags: Vec::new(),
+					alt: None,
+					name: None,
+					inactive: false,
+					stale: 0
 				}
 			)
 		}
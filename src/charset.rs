@@ -0,0 +1,140 @@
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use lazy_static::lazy_static;
+
+/// The characters that were allowed before the set could be configured
+const DEFAULT_CHARACTERS: &str = " !\"#$%&\'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~¥¨°²´·¿×ōπᓚᗢᘏ†•…‾∞≈≡⊞─│┌┏┐┓└┗┘┛├┣┤┫┬┳┴┻┼╂═║╒╔╕╗╘╚╛╜╝╟╠╢╣╤╥╦╧╩╫╭╮╰╱╲╿▀▁▂▃▄█▉▊▌▎▐░▒▓▔▙▛▜▟▪►◄◊◘◠☆☺♠♥♪♫♯⚵⚶⛭✥✽❀➅➐⠀⠁⠃⠈⠋⠘⠙⠛⠞⠟⠳⠺⠾⡀⡇⡞⡤⢀⢇⢠⢤⢦⢩⢫⢸⢹⢻⢾⢿⣀⣄⣆⣠⣤⣬⣯⣳⣴⣷⣻⣼⣽⣿";
+
+lazy_static! {
+	pub static ref DEFAULT_CHARSET: Charset = Charset::from_chars(DEFAULT_CHARACTERS.chars());
+}
+
+/// The characters that may be used in the art and the link mask of parcels
+/// Other characters are replaced with '?'
+/// The set is stored in the town json as a string of all its characters, so it is known how the parcels were made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Charset {
+	chars: BTreeSet<char>
+}
+
+impl Default for Charset {
+	fn default() -> Self {
+		DEFAULT_CHARSET.clone()
+	}
+}
+
+impl Charset {
+	/// A set of the given characters
+	/// Space and '?' are always included, since they are used to fill up lines and to replace other characters
+	pub fn from_chars(chars: impl IntoIterator<Item=char>) -> Self {
+		let mut chars: BTreeSet<char> = chars.into_iter().collect();
+		chars.insert(' ');
+		chars.insert('?');
+		Self { chars }
+	}
+
+	/// Parse a character set file
+	/// The file consists of entries separated by whitespace, where each entry is one of:
+	/// - a range of characters, like 'a..z'
+	/// - a range of code points, like 'U+2500..U+257F'
+	/// - a single code point, like 'U+2588'
+	/// - any other text, of which each character is allowed
+	///
+	/// An entry that is a single '#' makes the rest of the line a comment
+	pub fn parse(text: &str) -> Result<Self, String> {
+		let mut chars = BTreeSet::new();
+		for (row, line) in text.lines().enumerate() {
+			for entry in line.split_whitespace() {
+				if entry == "#" {
+					break;
+				}
+				let invalid = || format!("invalid entry on line {}: \"{}\"", row + 1, entry);
+				if let Some((start, end)) = parse_range(entry) {
+					let (start, end) = (start.ok_or_else(invalid)?, end.ok_or_else(invalid)?);
+					if start > end {
+						return Err(invalid());
+					}
+					chars.extend(start..=end);
+				} else if let Some(hex) = entry.strip_prefix("U+") {
+					chars.insert(parse_code_point(hex).ok_or_else(invalid)?);
+				} else {
+					chars.extend(entry.chars());
+				}
+			}
+		}
+		Ok(Self::from_chars(chars))
+	}
+
+	/// Read and parse a character set file
+	pub fn from_file(path: &Path) -> Result<Self, String> {
+		let text = fs::read_to_string(path).map_err(|err| format!("can't read {:?}: {}", path, err))?;
+		Self::parse(&text).map_err(|err| format!("{:?}: {}", path, err))
+	}
+
+	pub fn contains(&self, ch: char) -> bool {
+		self.chars.contains(&ch)
+	}
+
+	/// All characters in code point order
+	pub fn chars(&self) -> impl Iterator<Item=char> + '_ {
+		self.chars.iter().copied()
+	}
+}
+
+/// Split a range entry in its start and end, if the entry is a range
+fn parse_range(entry: &str) -> Option<(Option<char>, Option<char>)> {
+	let (start, end) = entry.split_once("..")?;
+	let parse_end = |txt: &str| match txt.strip_prefix("U+") {
+		Some(hex) => parse_code_point(hex),
+		None => crate::strutil::to_char(txt)
+	};
+	// a range has exactly one character or code point on both sides; anything else is a list of characters
+	if start.is_empty() || end.is_empty() || entry.matches("..").count() > 1 {
+		return None;
+	}
+	Some((parse_end(start), parse_end(end)))
+}
+
+fn parse_code_point(hex: &str) -> Option<char> {
+	char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+}
+
+impl Serialize for Charset {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where S: Serializer {
+		String::from_iter(&self.chars).serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for Charset {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where D: Deserializer<'de> {
+		Ok(Self::from_chars(String::deserialize(deserializer)?.chars()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_charset_file() {
+		let charset = Charset::parse("a..e xyz # comment ü\nU+2500..U+2502 U+2588\n#$%\n.. ...").unwrap();
+		assert_eq!(String::from_iter(charset.chars()), " #$%.?abcdexyz─━│█");
+		assert_eq!(Charset::parse("e..a").unwrap_err(), "invalid entry on line 1: \"e..a\"");
+		assert_eq!(Charset::parse("\nU+D800").unwrap_err(), "invalid entry on line 2: \"U+D800\"");
+		assert_eq!(Charset::parse("ab..c").unwrap_err(), "invalid entry on line 1: \"ab..c\"");
+	}
+
+	#[test]
+	fn default_charset() {
+		assert!(Charset::default().contains('╔'));
+		assert!(!Charset::default().contains('👻'));
+		let json = serde_json::to_string(&Charset::parse("ba").unwrap()).unwrap();
+		assert_eq!(json, "\" ?ab\"");
+		assert_eq!(serde_json::from_str::<Charset>(&json).unwrap(), Charset::from_chars("ab".chars()));
+	}
+}
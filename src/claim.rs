@@ -0,0 +1,70 @@
+
+use std::fmt;
+use crate::{
+	cadastre::{Cadastre, TOWN_WIDTH, TOWN_HEIGHT},
+	pos::Pos
+};
+
+/// Why a user can't claim a new plot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClaimError {
+	/// The user already holds as many other plots as the quota allows, so the new plot would be rejected
+	QuotaReached(usize),
+	/// There are no free plots left in the rendered town
+	NoFreePlots
+}
+
+impl fmt::Display for ClaimError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ClaimError::QuotaReached(quota) => write!(f, "You already hold {} plot(s) besides your parcel file, which is the most a user can hold", quota),
+			ClaimError::NoFreePlots => write!(f, "There are no free plots left")
+		}
+	}
+}
+
+/// Where a user claims a new plot: the requested plot if it's free, otherwise the free plot in the rendered town that is closest to it
+/// Without a request the plot closest to the center of the town is used
+/// The claim writes the main parcel file of the user, so only the other plots of the user count for the quota
+pub fn claim_location(cadastre: &Cadastre, username: &str, quota: usize, requested: Option<Pos>) -> Result<Pos, ClaimError> {
+	let others = cadastre.parcels_by(username)
+		.filter(|parcel| parcel.name.is_some())
+		.count();
+	if others >= quota {
+		return Err(ClaimError::QuotaReached(quota));
+	}
+	match requested {
+		Some(pos) if cadastre.parcel(pos).is_none() => Some(pos),
+		Some(pos) => cadastre.nearest_free(pos),
+		None => cadastre.nearest_free(Pos::new(TOWN_WIDTH as i64 / 2, TOWN_HEIGHT as i64 / 2))
+	}.ok_or(ClaimError::NoFreePlots)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Owner, Parcel};
+
+	#[test]
+	fn claim_requested_or_nearest_plot() {
+		let center = Pos::new(TOWN_WIDTH as i64 / 2, TOWN_HEIGHT as i64 / 2);
+		let town = Cadastre::empty()
+			.with_parcel(Parcel::empty(Owner::user("troido"), Pos::new(3, 4)))
+			.with_parcel(Parcel::empty(Owner::user("odiort"), center));
+		assert_eq!(claim_location(&town, "john", 1, Some(Pos::new(5, 5))), Ok(Pos::new(5, 5)));
+		// plots outside the rendered town can be requested too
+		assert_eq!(claim_location(&town, "john", 1, Some(Pos::new(-1, 40))), Ok(Pos::new(-1, 40)));
+		assert_eq!(claim_location(&town, "john", 1, Some(Pos::new(3, 4))), Ok(Pos::new(3, 3)));
+		assert_eq!(claim_location(&town, "john", 1, None), Ok(center - Pos::new(0, 1)));
+	}
+
+	#[test]
+	fn claim_within_quota() {
+		let town = Cadastre::empty()
+			.with_parcel(Parcel::empty(Owner::user("troido"), Pos::new(3, 4)))
+			.with_parcel(Parcel { name: Some("garden".to_string()), ..Parcel::empty(Owner::user("troido"), Pos::new(4, 4)) });
+		// the plot of the main parcel file moves to the new claim
+		assert_eq!(claim_location(&town, "troido", 2, Some(Pos::new(5, 5))), Ok(Pos::new(5, 5)));
+		assert_eq!(claim_location(&town, "troido", 1, Some(Pos::new(5, 5))), Err(ClaimError::QuotaReached(1)));
+	}
+}
@@ -0,0 +1,296 @@
+
+use std::fs;
+use std::fs::File;
+use std::io::{Write, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+use crate::{
+	config::{Config, QueryCommand, Query, ClaimCommand, PreviewCommand, EditCommand, ExportCommand, ImportCommand, ValidateCommand, CheckLinksCommand},
+	cadastre::{TOWN_WIDTH, TOWN_HEIGHT},
+	parcel::template_text,
+	Parcel,
+	Charset,
+	claim::claim_location,
+	export::{self, ExportTarget},
+	preview::preview_lines,
+	update::generate_cadastre,
+	editor::{self, Editor},
+	import::{import, ImportFormat},
+	linkcheck::{self, LinkChecker},
+	timeout::Limits,
+	HtmlOptions,
+	Cadastre,
+	Report,
+	Pos,
+	Owner,
+};
+
+pub fn init(config: &Config) {
+	write_file_safe(&config.town_json, serde_json::to_string(&Cadastre::empty()).expect("Failed to serialize cadastre"))
+		.expect("Failed to write town json file");
+}
+
+/// Read all parcels, write the new town json and its renders, and report what the admins should know
+pub fn update(config: &Config) {
+	let old: Cadastre = read_old_cadastre(config);
+	let mut report = Report::new();
+	let options = config.update_options(charset(config.allowed_characters.as_ref(), None));
+	let cadastre: Cadastre = generate_cadastre(&options, &old, &mut report);
+	write_file_safe(&config.town_json, serde_json::to_string(&cadastre).expect("Failed to serialize cadastre"))
+		.expect("Failed to write town json file");
+	render(config, &cadastre);
+	write_report(config.report.as_deref(), &report);
+}
+
+/// Render the existing town json again, for example after changing the html options
+pub fn render_town(config: &Config) {
+	let cadastre: Cadastre = read_old_cadastre(config);
+	render(config, &cadastre);
+}
+
+pub fn query(command: &QueryCommand) {
+	let cadastre: Cadastre = read_old_cadastre(&command.config);
+	let result = match &command.query {
+		Query::Owner { name } => serde_json::json!(cadastre.find_by_owner(name)),
+		Query::Search { text } => serde_json::json!(cadastre.search(text)),
+		Query::Tag { tag } => serde_json::json!(cadastre.find_by_tag(tag)),
+		Query::At { x, y } => serde_json::json!(cadastre.parcel(Pos::new(*x, *y))),
+		Query::Neighbours { x, y } => serde_json::json!(cadastre.neighbours(Pos::new(*x, *y))),
+		Query::Free { x1, y1, x2, y2 } => serde_json::json!(cadastre.free_plots(Pos::new(*x1, *y1), Pos::new(*x2, *y2))),
+		Query::Near { x, y } => serde_json::json!(cadastre.nearest_free(Pos::new(*x, *y)))
+	};
+	println!("{}", serde_json::to_string_pretty(&result).expect("Failed to serialize query result"));
+}
+
+fn read_old_cadastre(config: &Config) -> Cadastre {
+	serde_json::from_str(
+		fs::read_to_string(config.town_json_old.clone().unwrap_or(config.town_json.clone()))
+			.expect("Unable to read existing town json file")
+			.as_str()
+	).expect("Existing town file is not valid json")
+}
+
+fn render(config: &Config, cadastre: &Cadastre) {
+	let mut text_file = File::create(&config.txt_render).expect("Failed to open file for txt render");
+	cadastre.render_text(TOWN_WIDTH, TOWN_HEIGHT, |txt| text_file.write_all(txt.as_bytes()).expect("Failed to write txt render to file"));
+	let options = HtmlOptions {
+		broken_links: read_broken_links(config),
+		template: config.html_template.as_ref().map(|path| fs::read_to_string(path).expect("Failed to read html template")),
+		theme: config.html_theme,
+		title: config.html_title.clone(),
+		generated: Some(SystemTime::now()),
+		interactive: config.html_interactive,
+		accessible: config.html_accessible
+	};
+	let mut html_file = File::create(&config.html_render).expect("Failed to open file for html render");
+	cadastre.render_html_with(TOWN_WIDTH, TOWN_HEIGHT, &options, |html| html_file.write_all(html.as_bytes()).expect("Failed to write html render to file"));
+}
+
+/// The urls that the last link check found to be broken; none if there was no link check yet
+fn read_broken_links(config: &Config) -> HashSet<String> {
+	let Some(path) = &config.broken_links else {
+		return HashSet::new();
+	};
+	match fs::read_to_string(path) {
+		Ok(text) => serde_json::from_str(&text).expect("Broken links file is not valid json"),
+		Err(err) if err.kind() == ErrorKind::NotFound => HashSet::new(),
+		Err(err) => panic!("Failed to read broken links file: {}", err)
+	}
+}
+
+pub fn check_links(command: &CheckLinksCommand) {
+	let config = &command.config;
+	let cadastre: Cadastre = read_old_cadastre(config);
+	let checker = LinkChecker {
+		local_urls: command.local_url.clone(),
+		homedirs: config.homedirs.clone(),
+		public_dir_in_home: command.public_dir_in_home.clone(),
+		limits: Limits { read_timeout: Duration::from_secs_f64(config.read_timeout), deadline: None },
+		drop_privileges: config.drop_privileges,
+		http: command.http.then(|| LinkChecker::http_agent(Duration::from_secs_f64(command.http_timeout)))
+	};
+	let mut report = Report::new();
+	let summary = linkcheck::check_town(&cadastre, &checker, &mut report);
+	write_report(command.link_report.as_deref(), &report);
+	eprintln!("{}", summary);
+	if let Some(path) = &config.broken_links {
+		let mut broken: Vec<&String> = summary.broken_urls.iter().collect();
+		broken.sort();
+		write_file_safe(path, serde_json::to_string_pretty(&broken).expect("Failed to serialize broken links"))
+			.expect("Failed to write broken links file");
+	}
+}
+
+pub fn claim_plot(claim: &ClaimCommand) {
+	let cadastre: Cadastre = read_old_cadastre(&claim.config);
+	let Some(Owner::User(username)) = Owner::from_homedir(&claim.home) else {
+		panic!("Can't find username from home dir {:?}", claim.home);
+	};
+	let requested = claim.x.zip(claim.y).map(|(x, y)| Pos::new(x, y));
+	let location = match claim_location(&cadastre, &username, claim.config.plot_quota, requested) {
+		Ok(location) => location,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	};
+	if let Some(requested) = requested.filter(|requested| *requested != location) {
+		println!("Plot {} {} is already claimed, using {} {} instead", requested.x, requested.y, location.x, location.y);
+	}
+	let path = claim.home.join(&claim.config.parcel_in_home);
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir).expect("Failed to create directory for parcel file");
+	}
+	let text = template_text(location, &username, &claim.link.replace("{user}", &username));
+	write_parcel_file(&path, &text, claim.force);
+	println!("Claimed plot {} {} in {:?}", location.x, location.y, path);
+	println!("Edit that file to draw your art; the town is updated periodically");
+}
+
+/// The allowed characters from the given file, or otherwise the ones the town was built with
+fn charset(path: Option<&PathBuf>, cadastre: Option<&Cadastre>) -> Charset {
+	match path {
+		Some(path) => Charset::from_file(path).unwrap_or_else(|err| panic!("Failed to load allowed characters: {}", err)),
+		None => cadastre.map(|cadastre| cadastre.charset().clone()).unwrap_or_default()
+	}
+}
+
+/// Read a parcel file, printing the characters that were replaced as warnings
+fn read_parcel_file(path: &Path, user: &str, charset: &Charset) -> Parcel {
+	let text = fs::read_to_string(path).expect("Failed to read parcel file");
+	match Parcel::from_text_with_charset(&text, Owner::user(user), charset) {
+		Ok((parcel, replacements)) => {
+			for replacement in replacements {
+				eprintln!("Warning: {}", replacement);
+			}
+			parcel
+		}
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	}
+}
+
+pub fn validate_parcel(validate: &ValidateCommand) {
+	let cadastre = read_old_cadastre(&validate.config);
+	let charset = charset(validate.config.allowed_characters.as_ref(), Some(&cadastre));
+	let text = fs::read_to_string(&validate.file).expect("Failed to read parcel file");
+	match Parcel::from_text_with_charset(&text, Owner::user(&validate.user), &charset) {
+		Ok((parcel, replacements)) if replacements.is_empty() => {
+			println!("{:?} is a valid parcel for plot {} {}", validate.file, parcel.location.x, parcel.location.y);
+		}
+		Ok((_, replacements)) => {
+			println!("{} character(s) in {:?} can't be used:", replacements.len(), validate.file);
+			for replacement in replacements {
+				println!("  {}", replacement);
+			}
+			std::process::exit(1);
+		}
+		Err(err) => {
+			println!("{}", err);
+			std::process::exit(1);
+		}
+	}
+}
+
+pub fn edit_parcel(edit: &EditCommand) {
+	let cadastre = read_old_cadastre(&edit.config);
+	let charset = charset(edit.config.allowed_characters.as_ref(), Some(&cadastre));
+	let parcel = read_parcel_file(&edit.file, &edit.user, &charset);
+	editor::run(Editor::new(parcel, &cadastre, charset), &edit.file).expect("Failed to run editor");
+}
+
+/// Write a new parcel file, exiting when it exists and overwriting is not forced
+fn write_parcel_file(path: &Path, text: &str, force: bool) {
+	match export::write_parcel_file(path, text, force) {
+		Ok(()) => {}
+		Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+			eprintln!("{:?} already exists; use --force to overwrite it", path);
+			std::process::exit(1);
+		}
+		Err(err) => panic!("Failed to write parcel file {:?}: {}", path, err)
+	}
+}
+
+pub fn export_parcels(command: &ExportCommand) {
+	let cadastre = read_old_cadastre(&command.config);
+	let target = ExportTarget::from_args(&command.target, command.y).unwrap_or_else(|err| panic!("{}", err));
+	let parcels: Vec<&Parcel> = target.select(&cadastre);
+	if parcels.is_empty() {
+		match target {
+			ExportTarget::Plot(pos) => eprintln!("There is no plot at {} {}", pos.x, pos.y),
+			ExportTarget::Owner(name) => eprintln!("{} has no plots", name)
+		}
+		std::process::exit(1);
+	}
+	if command.output.is_some() && parcels.len() > 1 {
+		eprintln!("{} has {} plots, which can't all be written to one file", target, parcels.len());
+		std::process::exit(1);
+	}
+	for parcel in parcels {
+		let path: PathBuf = command.output.clone().unwrap_or_else(|| export::default_path(parcel));
+		write_parcel_file(&path, &parcel.to_text(), command.force);
+		println!("Wrote plot {} {} of {:?} to {:?}", parcel.location.x, parcel.location.y, parcel.owner, path);
+	}
+}
+
+pub fn import_parcel(command: &ImportCommand) {
+	let source = fs::read(&command.source).expect("Failed to read source file");
+	let format = command.format.unwrap_or_else(|| ImportFormat::from_path(&command.source));
+	let charset = charset(command.allowed_characters.as_ref(), None);
+	let art = match import(&source, format, command.fit, command.anchor, command.invert, &charset) {
+		Ok((art, replacements)) => {
+			for replacement in replacements {
+				eprintln!("Warning: {}", replacement);
+			}
+			art
+		}
+		Err(err) => {
+			eprintln!("Can't convert {:?}: {}", command.source, err);
+			std::process::exit(1);
+		}
+	};
+	// the owner is decided by where the parcel file ends up
+	let parcel = Parcel { mask: art.clone(), art, ..Parcel::empty(Owner::Public, Pos::new(command.x, command.y)) };
+	match &command.output {
+		Some(path) => write_parcel_file(path, &parcel.to_text(), command.force),
+		None => print!("{}", parcel.to_text())
+	}
+}
+
+pub fn preview_parcel(preview: &PreviewCommand) {
+	let cadastre = read_old_cadastre(&preview.config);
+	let parcel = read_parcel_file(&preview.file, &preview.user, &charset(preview.config.allowed_characters.as_ref(), Some(&cadastre)));
+	let location = parcel.location;
+	let cadastre = cadastre.with_parcel(parcel);
+	for line in preview_lines(&cadastre, location, preview.highlight) {
+		println!("{}", line);
+	}
+}
+
+fn write_report(path: Option<&Path>, report: &Report) {
+	eprint!("{}", report);
+	if let Some(path) = path {
+		write_file_safe(path, report.to_string()).expect("Failed to write report file");
+	}
+}
+
+fn write_file_safe<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<(), std::io::Error> {
+	let temppath = path
+		.as_ref()
+		.with_file_name(
+			format!(
+				".{}.tmp",
+				path.as_ref()
+					.file_name()
+					.ok_or_else(|| std::io::Error::other("Can't write a to a directory"))?
+					.to_str()
+					.unwrap_or("invalid")
+			)
+		);
+	fs::write(&temppath, contents)?;
+	fs::rename(&temppath, path)?;
+	Ok(())
+}
@@ -1,6 +1,13 @@
 
 use std::path::PathBuf;
+use std::time::Duration;
 use clap::{Parser, Subcommand, Args};
+use crate::{
+	cadastre::{TOWN_WIDTH, TOWN_HEIGHT, HtmlTheme},
+	import::{ImportFormat, Fit, Anchor},
+	charset::Charset,
+	update::{InactivePolicy, UpdateOptions}
+};
 
 #[derive(Debug, Args)]
 pub struct Config {
@@ -13,10 +20,70 @@ pub struct Config {
 	#[arg(long, default_value=".cadastre/home.txt", env="CADASTRE_TOWN_JSON_PATH")]
 	pub parcel_in_home: PathBuf,
 
+	/// the directory within a user's own home dir where any additional parcels (ending in .prcl) are placed
+	#[arg(long, default_value=".cadastre/", env="CADASTRE_PARCEL_DIR_IN_HOME")]
+	pub parcel_dir_in_home: PathBuf,
+
+	/// the file within a user's own home dir that lists the users whose shared parcels they agree to co-own
+	#[arg(long, default_value=".cadastre/shared.txt", env="CADASTRE_SHARE_CONFIRMATION_IN_HOME")]
+	pub share_confirmation_in_home: PathBuf,
+
+	/// the file within a user's own home dir that names the users to transfer their plots to
+	#[arg(long, default_value=".cadastre/transfer.txt", env="CADASTRE_TRANSFER_IN_HOME")]
+	pub transfer_in_home: PathBuf,
+
 	/// the location of the admin parcel
 	#[arg(long, env="CADASTRE_ADMIN_PARCEL_FILE")]
 	pub admin_parcel: Vec<PathBuf>,
 
+	/// users whose parcels are never read, separated by commas
+	#[arg(long, value_delimiter=',', env="CADASTRE_DENIED_USERS")]
+	pub denied_users: Vec<String>,
+
+	/// if any users are given, only the parcels of these users are read; separated by commas
+	#[arg(long, value_delimiter=',', env="CADASTRE_ALLOWED_USERS")]
+	pub allowed_users: Vec<String>,
+
+	/// the maximum number of plots that a single user can hold, including additional parcels
+	#[arg(long, default_value="1", env="CADASTRE_PLOT_QUOTA")]
+	pub plot_quota: usize,
+
+	/// consider users inactive if neither their parcel file nor their home dir has been modified for this many days
+	#[arg(long, env="CADASTRE_INACTIVE_AFTER_DAYS")]
+	pub inactive_after_days: Option<u64>,
+
+	/// what to do with the parcels of inactive users
+	#[arg(long, value_enum, default_value_t=InactivePolicy::Mark, env="CADASTRE_INACTIVE_POLICY")]
+	pub inactive_policy: InactivePolicy,
+
+	/// the maximum size in bytes of any file that is read, such as parcel files
+	#[arg(long, default_value="65536", env="CADASTRE_MAX_FILE_SIZE")]
+	pub max_file_size: u64,
+
+	/// a file listing the characters that may be used in parcels, as ranges like 'a..z' or 'U+2500..U+257F' and as individual characters; updates use a built-in set by default, other commands the set recorded in the town json
+	#[arg(long, env="CADASTRE_ALLOWED_CHARACTERS")]
+	pub allowed_characters: Option<PathBuf>,
+
+	/// read files in home dirs from a helper process that runs as the owner of the home dir
+	#[arg(long, env="CADASTRE_DROP_PRIVILEGES")]
+	pub drop_privileges: bool,
+
+	/// the maximum number of seconds to wait for reading a single file or directory
+	#[arg(long, default_value="10", env="CADASTRE_READ_TIMEOUT", value_parser=parse_seconds)]
+	pub read_timeout: f64,
+
+	/// the maximum number of seconds that reading all parcels can take; any files after that are skipped
+	#[arg(long, env="CADASTRE_RUN_DEADLINE", value_parser=parse_seconds)]
+	pub run_deadline: Option<f64>,
+
+	/// keep the previous parcels of a user when their files are skipped because they took too long
+	#[arg(long, env="CADASTRE_KEEP_ON_TIMEOUT")]
+	pub keep_on_timeout: bool,
+
+	/// when a user's parcel file fails to parse, keep their previous parcel for at most this many updates
+	#[arg(long, env="CADASTRE_STICKY_RUNS")]
+	pub sticky_runs: Option<u32>,
+
 	/// the directories for public parcels
 	#[arg(long, env="CADASTRE_PUBLIC_PARCELS_DIRS")]
 	pub public_parcels: Vec<PathBuf>,
@@ -29,12 +96,67 @@ pub struct Config {
 	#[arg(long, env="CADASTRE_TOWN_JSON_OLD_FILE")]
 	pub town_json_old: Option<PathBuf>,
 
+	/// a json file with the urls that check-links found to be broken; check-links writes it, and renders give links to those urls the 'broken' class
+	#[arg(long, env="CADASTRE_BROKEN_LINKS_FILE")]
+	pub broken_links: Option<PathBuf>,
+
+	/// location to write the update report, in addition to printing it
+	#[arg(long, env="CADASTRE_REPORT_FILE")]
+	pub report: Option<PathBuf>,
+
 	/// location to write town.txt
 	#[arg(long, default_value="./town.txt", env="CADASTRE_TXT_RENDER_FILE")]
 	pub txt_render: PathBuf,
 	/// location to write town.html
 	#[arg(long, default_value="./town.html", env="CADASTRE_HTML_RENDER_FILE")]
-	pub html_render: PathBuf
+	pub html_render: PathBuf,
+
+	/// a page to render the town in instead of the built-in one, where {{map}}, {{title}}, {{style}} and {{generated}} are replaced by the town, the title, the stylesheet and the time of the render
+	#[arg(long, env="CADASTRE_HTML_TEMPLATE")]
+	pub html_template: Option<PathBuf>,
+
+	/// the colours of the html render
+	#[arg(long, value_enum, default_value_t=HtmlTheme::Plain, env="CADASTRE_HTML_THEME")]
+	pub html_theme: HtmlTheme,
+
+	/// add a plot index to the html render, and a script that shows who owns the plot under the mouse, a minimap and zoom buttons
+	#[arg(long, env="CADASTRE_HTML_INTERACTIVE")]
+	pub html_interactive: bool,
+
+	/// label the plots in the html render with their alt text for screen readers, and add a list of the plots with their links
+	#[arg(long, env="CADASTRE_HTML_ACCESSIBLE")]
+	pub html_accessible: bool,
+
+	/// the title of the html render, for templates that use {{title}}
+	#[arg(long, default_value="Cadastre", env="CADASTRE_HTML_TITLE")]
+	pub html_title: String
+}
+
+impl Config {
+	/// The options for reading the parcels in an update, with the characters that are allowed in them
+	pub fn update_options(&self, charset: Charset) -> UpdateOptions {
+		UpdateOptions {
+			homedirs: self.homedirs.clone(),
+			parcel_in_home: self.parcel_in_home.clone(),
+			parcel_dir_in_home: self.parcel_dir_in_home.clone(),
+			share_confirmation_in_home: self.share_confirmation_in_home.clone(),
+			transfer_in_home: self.transfer_in_home.clone(),
+			admin_parcel: self.admin_parcel.clone(),
+			public_parcels: self.public_parcels.clone(),
+			denied_users: self.denied_users.clone(),
+			allowed_users: self.allowed_users.clone(),
+			plot_quota: self.plot_quota,
+			inactive_after_days: self.inactive_after_days,
+			inactive_policy: self.inactive_policy,
+			max_file_size: self.max_file_size,
+			charset,
+			drop_privileges: self.drop_privileges,
+			read_timeout: Duration::from_secs_f64(self.read_timeout),
+			run_deadline: self.run_deadline.map(Duration::from_secs_f64),
+			keep_on_timeout: self.keep_on_timeout,
+			sticky_runs: self.sticky_runs
+		}
+	}
 }
 
 #[derive(Debug, Parser)]
@@ -53,5 +175,279 @@ pub enum Action {
 	/// Update cadastre world with townie data
 	Update(Config),
 	/// Create html and txt render of existing town json
-	Render(Config)
+	Render(Config),
+	/// Look up plots in the existing town json and print the result as json
+	Query(QueryCommand),
+	/// Create a parcel file in your home dir for a free plot
+	Claim(ClaimCommand),
+	/// Show how a parcel file looks between its neighbours in the existing town
+	Preview(PreviewCommand),
+	/// Edit a parcel file in an interactive terminal editor
+	Edit(EditCommand),
+	/// Write plots from the town json as parcel files
+	Export(ExportCommand),
+	/// Convert text, ANSI art or an image into a parcel file
+	Import(ImportCommand),
+	/// Check a parcel file for errors and for characters that will be replaced
+	Validate(ValidateCommand),
+	/// Find links in the existing town json that don't go anywhere, and report them per owner
+	CheckLinks(CheckLinksCommand)
+}
+
+#[derive(Debug, Args)]
+pub struct PreviewCommand {
+	#[command(flatten)]
+	pub config: Config,
+
+	/// the name of the owner of the parcel
+	#[arg(long, env="USER")]
+	pub user: String,
+
+	/// highlight the border of the parcel with terminal colours
+	#[arg(long)]
+	pub highlight: bool,
+
+	/// the parcel file to preview
+	pub file: PathBuf
+}
+
+#[derive(Debug, Args)]
+pub struct ClaimCommand {
+	#[command(flatten)]
+	pub config: Config,
+
+	/// the home dir to create the parcel file in
+	#[arg(long, env="HOME")]
+	pub home: PathBuf,
+
+	/// the url that the signature in the template links to; {user} is replaced by the username
+	#[arg(long, default_value="https://tilde.town/~{user}/", env="CADASTRE_CLAIM_LINK")]
+	pub link: String,
+
+	/// overwrite an existing parcel file
+	#[arg(long)]
+	pub force: bool,
+
+	/// the horizontal position of the plot to claim; by default the free plot closest to the center of the town
+	#[arg(allow_negative_numbers=true, requires="y")]
+	pub x: Option<i64>,
+
+	/// the vertical position of the plot to claim
+	#[arg(allow_negative_numbers=true)]
+	pub y: Option<i64>
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateCommand {
+	#[command(flatten)]
+	pub config: Config,
+
+	/// the name of the owner of the parcel
+	#[arg(long, env="USER")]
+	pub user: String,
+
+	/// the parcel file to check
+	pub file: PathBuf
+}
+
+#[derive(Debug, Args)]
+pub struct CheckLinksCommand {
+	#[command(flatten)]
+	pub config: Config,
+
+	/// urls of pages in the home dirs, with {user} for the username; these links are checked by looking for the file instead of over the network
+	#[arg(long, default_values=["https://tilde.town/~{user}/", "http://tilde.town/~{user}/"], env="CADASTRE_LOCAL_URLS")]
+	pub local_url: Vec<String>,
+
+	/// the directory within a user's home dir that their local urls point to
+	#[arg(long, default_value="public_html", env="CADASTRE_PUBLIC_DIR_IN_HOME")]
+	pub public_dir_in_home: PathBuf,
+
+	/// also check links to other http and https urls by sending a HEAD request
+	#[arg(long)]
+	pub http: bool,
+
+	/// the maximum number of seconds to wait for the response to a HEAD request
+	#[arg(long, default_value="10", value_parser=parse_seconds)]
+	pub http_timeout: f64,
+
+	/// location to write the link report, in addition to printing it
+	#[arg(long, env="CADASTRE_LINK_REPORT_FILE")]
+	pub link_report: Option<PathBuf>
+}
+
+#[derive(Debug, Args)]
+pub struct EditCommand {
+	#[command(flatten)]
+	pub config: Config,
+
+	/// the name of the owner of the parcel
+	#[arg(long, env="USER")]
+	pub user: String,
+
+	/// the parcel file to edit; create one with the claim command first
+	pub file: PathBuf
+}
+
+#[derive(Debug, Args)]
+pub struct ExportCommand {
+	#[command(flatten)]
+	pub config: Config,
+
+	/// the file to write to; by default each plot is written to x_y.prcl in the current directory
+	#[arg(long, short)]
+	pub output: Option<PathBuf>,
+
+	/// overwrite existing files
+	#[arg(long)]
+	pub force: bool,
+
+	/// the horizontal position of the plot, or the name of a user to export all their plots
+	#[arg(allow_negative_numbers=true)]
+	pub target: String,
+
+	/// the vertical position of the plot
+	#[arg(allow_negative_numbers=true)]
+	pub y: Option<i64>
+}
+
+#[derive(Debug, Args)]
+pub struct ImportCommand {
+	/// the file to convert
+	pub source: PathBuf,
+
+	/// the horizontal position of the plot in the new parcel file
+	#[arg(allow_negative_numbers=true)]
+	pub x: i64,
+
+	/// the vertical position of the plot in the new parcel file
+	#[arg(allow_negative_numbers=true)]
+	pub y: i64,
+
+	/// the format of the source; by default it is guessed from the file extension
+	#[arg(long, value_enum)]
+	pub format: Option<ImportFormat>,
+
+	/// what to do with sources that are larger than a plot
+	#[arg(long, value_enum, default_value_t=Fit::Crop)]
+	pub fit: Fit,
+
+	/// where to put the source in the plot when it doesn't fill the plot exactly
+	#[arg(long, value_enum, default_value_t=Anchor::Center)]
+	pub anchor: Anchor,
+
+	/// use dense characters for dark pixels instead of bright pixels
+	#[arg(long)]
+	pub invert: bool,
+
+	/// a file listing the characters that may be used in parcels; by default the built-in set
+	#[arg(long, env="CADASTRE_ALLOWED_CHARACTERS")]
+	pub allowed_characters: Option<PathBuf>,
+
+	/// the parcel file to write; by default the parcel is printed
+	#[arg(long, short)]
+	pub output: Option<PathBuf>,
+
+	/// overwrite an existing output file
+	#[arg(long)]
+	pub force: bool
+}
+
+#[derive(Debug, Args)]
+pub struct QueryCommand {
+	#[command(flatten)]
+	pub config: Config,
+
+	#[command(subcommand)]
+	pub query: Query
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Query {
+	/// All plots that a user owns or co-owns
+	Owner {
+		name: String
+	},
+	/// All plots whose title, description or tags contain a text
+	Search {
+		text: String
+	},
+	/// All plots with a tag
+	Tag {
+		tag: String
+	},
+	/// The plot at a position
+	At {
+		#[arg(allow_negative_numbers=true)]
+		x: i64,
+		#[arg(allow_negative_numbers=true)]
+		y: i64
+	},
+	/// The claimed plots around a position
+	Neighbours {
+		#[arg(allow_negative_numbers=true)]
+		x: i64,
+		#[arg(allow_negative_numbers=true)]
+		y: i64
+	},
+	/// All unclaimed plots between two corners; by default the rendered part of the town
+	Free {
+		#[arg(allow_negative_numbers=true, default_value_t=0)]
+		x1: i64,
+		#[arg(allow_negative_numbers=true, default_value_t=0)]
+		y1: i64,
+		#[arg(allow_negative_numbers=true, default_value_t=TOWN_WIDTH as i64 - 1)]
+		x2: i64,
+		#[arg(allow_negative_numbers=true, default_value_t=TOWN_HEIGHT as i64 - 1)]
+		y2: i64
+	},
+	/// The unclaimed plot in the rendered part of the town that is closest to a position
+	Near {
+		#[arg(allow_negative_numbers=true)]
+		x: i64,
+		#[arg(allow_negative_numbers=true)]
+		y: i64
+	}
+}
+
+/// A number of seconds that can be turned into a duration: not negative, not NaN and not too large
+fn parse_seconds(text: &str) -> Result<f64, String> {
+	let seconds: f64 = text.parse().map_err(|err| format!("{}", err))?;
+	Duration::try_from_secs_f64(seconds).map_err(|_| format!("{} is not a valid number of seconds", text))?;
+	Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_valid_seconds() {
+		assert_eq!(parse_seconds("10"), Ok(10.0));
+		assert_eq!(parse_seconds("0.5"), Ok(0.5));
+		assert_eq!(parse_seconds("0"), Ok(0.0));
+		assert!(parse_seconds("-1").is_err());
+		assert!(parse_seconds("NaN").is_err());
+		assert!(parse_seconds("inf").is_err());
+		assert!(parse_seconds("1e30").is_err());
+		assert!(parse_seconds("ten").is_err());
+	}
+
+	#[test]
+	fn split_user_lists() {
+		// no other test reads these variables
+		std::env::set_var("CADASTRE_DENIED_USERS", "troido,odiort");
+		std::env::set_var("CADASTRE_ALLOWED_USERS", "john");
+		let Action::Update(config) = Command::parse_from(["cadastrs", "update"]).action else {
+			panic!("expected the update command");
+		};
+		std::env::remove_var("CADASTRE_DENIED_USERS");
+		std::env::remove_var("CADASTRE_ALLOWED_USERS");
+		assert_eq!(config.denied_users, vec!["troido".to_string(), "odiort".to_string()]);
+		assert_eq!(config.allowed_users, vec!["john".to_string()]);
+		let Action::Update(config) = Command::parse_from(["cadastrs", "update", "--denied-users", "a,b", "--denied-users", "c"]).action else {
+			panic!("expected the update command");
+		};
+		assert_eq!(config.denied_users, vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+	}
 }
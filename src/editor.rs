@@ -0,0 +1,413 @@
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use crossterm::{
+	cursor,
+	event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+	style::{Attribute, Print, SetAttribute},
+	terminal::{self, ClearType},
+	QueueableCommand,
+};
+use crate::{
+	cadastre::Cadastre,
+	charset::Charset,
+	link::Link,
+	parcel::{Parcel, char_width, line_cells, PLOT_WIDTH, PLOT_HEIGHT},
+	pos::Pos
+};
+
+/// How many characters of the neighbouring plots are shown left and right of the plot that is edited
+const MARGIN_X: usize = 6;
+/// How many lines of the neighbouring plots are shown above and below the plot that is edited
+const MARGIN_Y: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+	Art,
+	Mask
+}
+
+/// What the caller should do after a key has been handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+	Continue,
+	Save,
+	Quit
+}
+
+/// The state of the parcel editor, independent of the terminal
+#[derive(Debug, Clone)]
+pub struct Editor {
+	pub parcel: Parcel,
+	pub layer: Layer,
+	/// Position of the cursor within the plot
+	pub cursor: (usize, usize),
+	charset: Charset,
+	palette: Vec<char>,
+	brush: usize,
+	/// The link that is being typed for this mask key
+	prompt: Option<(char, String)>,
+	/// The cells of the 3 by 3 plots around the edited plot, without the plot itself
+	surroundings: Vec<Vec<Option<char>>>,
+	pub modified: bool,
+	quit_requested: bool,
+	pub message: String
+}
+
+impl Editor {
+	pub fn new(parcel: Parcel, cadastre: &Cadastre, charset: Charset) -> Self {
+		let corner = parcel.location - Pos::new(1, 1);
+		let mut surroundings = Vec::with_capacity(3 * PLOT_HEIGHT);
+		for plot_y in corner.y..(corner.y + 3) {
+			for inner_y in 0..PLOT_HEIGHT {
+				let mut line = Vec::with_capacity(3 * PLOT_WIDTH);
+				for plot_x in corner.x..(corner.x + 3) {
+					line.extend(line_cells(&cadastre.plot_text_line(Pos::new(plot_x, plot_y), inner_y)));
+				}
+				surroundings.push(line);
+			}
+		}
+		Self {
+			parcel,
+			layer: Layer::Art,
+			cursor: (0, 0),
+			palette: charset.chars().collect(),
+			charset,
+			brush: 0,
+			prompt: None,
+			surroundings,
+			modified: false,
+			quit_requested: false,
+			message: String::new()
+		}
+	}
+
+	pub fn brush(&self) -> char {
+		self.palette[self.brush]
+	}
+
+	fn lines(&self) -> &Vec<String> {
+		match self.layer {
+			Layer::Art => &self.parcel.art,
+			Layer::Mask => &self.parcel.mask
+		}
+	}
+
+	/// The character that covers a cell, which is the same for both cells of a wide character
+	fn char_at(lines: &[String], (x, y): (usize, usize)) -> char {
+		lines[y].chars()
+			.flat_map(|ch| std::iter::repeat_n(ch, char_width(ch)))
+			.nth(x)
+			.unwrap_or(' ')
+	}
+
+	/// The link key in the mask under the cursor, if it has a link
+	pub fn link_under_cursor(&self) -> Option<(char, &str)> {
+		let key = Self::char_at(&self.parcel.mask, self.cursor);
+		self.parcel.links.get(&key).map(|link| (key, link.url.as_str()))
+	}
+
+	fn draw_char(&mut self, ch: char) {
+		if !self.charset.contains(ch) {
+			self.message = format!("'{}' is not an allowed character", ch);
+			return;
+		}
+		let (x, y) = self.cursor;
+		if x + char_width(ch) > PLOT_WIDTH {
+			self.message = format!("'{}' is too wide for the last cell", ch);
+			return;
+		}
+		let lines = match self.layer {
+			Layer::Art => &mut self.parcel.art,
+			Layer::Mask => &mut self.parcel.mask
+		};
+		let mut cells = line_cells(&lines[y]);
+		set_cell(&mut cells, x, ch);
+		lines[y] = cells.into_iter().flatten().collect();
+		self.modified = true;
+	}
+
+	fn move_cursor(&mut self, dx: i64, dy: i64) {
+		let (x, y) = self.cursor;
+		self.cursor = (
+			(x as i64 + dx).clamp(0, PLOT_WIDTH as i64 - 1) as usize,
+			(y as i64 + dy).clamp(0, PLOT_HEIGHT as i64 - 1) as usize
+		);
+	}
+
+	pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+		if let Some((mask_key, mut link)) = self.prompt.take() {
+			match key.code {
+				KeyCode::Enter if link.trim().is_empty() => {
+					self.parcel.links.remove(&mask_key);
+					self.modified = true;
+					self.message = format!("removed the link for '{}'", mask_key);
+				}
+				KeyCode::Enter => {
+					self.parcel.links.insert(mask_key, Link::parse(&link));
+					self.modified = true;
+					self.message = format!("'{}' links to {}", mask_key, link.trim());
+				}
+				KeyCode::Esc => {
+					self.message.clear();
+				}
+				KeyCode::Backspace => {
+					link.pop();
+					self.prompt = Some((mask_key, link));
+				}
+				KeyCode::Char(ch) => {
+					link.push(ch);
+					self.prompt = Some((mask_key, link));
+				}
+				_ => {
+					self.prompt = Some((mask_key, link));
+				}
+			}
+			return Action::Continue;
+		}
+		let quit_requested = std::mem::take(&mut self.quit_requested);
+		let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+		match key.code {
+			KeyCode::Char('s') if ctrl => return Action::Save,
+			KeyCode::Char('q') if ctrl => return self.quit(quit_requested),
+			KeyCode::Esc => return self.quit(quit_requested),
+			KeyCode::Char('l') if ctrl => {
+				let mask_key = Self::char_at(&self.parcel.mask, self.cursor);
+				if mask_key == ' ' {
+					self.message = "put a link key in the mask layer first".to_string();
+				} else {
+					let link = self.parcel.links.get(&mask_key).map(Link::to_text).unwrap_or_default();
+					self.prompt = Some((mask_key, link));
+				}
+			}
+			KeyCode::Char(_) if ctrl => {}
+			KeyCode::Char(ch) => {
+				self.draw_char(ch);
+				self.move_cursor(char_width(ch) as i64, 0);
+			}
+			KeyCode::Enter => {
+				self.draw_char(self.brush());
+				self.move_cursor(char_width(self.brush()) as i64, 0);
+			}
+			KeyCode::Backspace => {
+				self.move_cursor(-1, 0);
+				self.draw_char(' ');
+			}
+			KeyCode::Delete => self.draw_char(' '),
+			KeyCode::Tab => {
+				self.layer = match self.layer {
+					Layer::Art => Layer::Mask,
+					Layer::Mask => Layer::Art
+				};
+			}
+			KeyCode::PageUp => self.brush = (self.brush + self.palette.len() - 1) % self.palette.len(),
+			KeyCode::PageDown => self.brush = (self.brush + 1) % self.palette.len(),
+			KeyCode::Left => self.move_cursor(-1, 0),
+			KeyCode::Right => self.move_cursor(1, 0),
+			KeyCode::Up => self.move_cursor(0, -1),
+			KeyCode::Down => self.move_cursor(0, 1),
+			KeyCode::Home => self.cursor.0 = 0,
+			KeyCode::End => self.cursor.0 = PLOT_WIDTH - 1,
+			_ => {}
+		}
+		Action::Continue
+	}
+
+	/// Quitting with unsaved changes needs to be confirmed by quitting again directly after
+	fn quit(&mut self, quit_requested: bool) -> Action {
+		if !self.modified || quit_requested {
+			return Action::Quit;
+		}
+		self.quit_requested = true;
+		self.message = "there are unsaved changes; press again to quit without saving".to_string();
+		Action::Continue
+	}
+
+	fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+		out.queue(terminal::Clear(ClearType::All))?;
+		let lines = self.lines();
+		let (left, right) = (PLOT_WIDTH - MARGIN_X, 2 * PLOT_WIDTH + MARGIN_X);
+		for row in 0..(PLOT_HEIGHT + 2 * MARGIN_Y) {
+			out.queue(cursor::MoveTo(0, row as u16))?;
+			let y = PLOT_HEIGHT - MARGIN_Y + row;
+			let cells = lines.get(y.wrapping_sub(PLOT_HEIGHT)).map(|line| line_cells(line)).unwrap_or_default();
+			for x in left..right {
+				let inner = (x.wrapping_sub(PLOT_WIDTH), y.wrapping_sub(PLOT_HEIGHT));
+				if inner.0 >= PLOT_WIDTH || inner.1 >= PLOT_HEIGHT {
+					// the visible part of the surroundings can start or end halfway a wide character
+					let ch = match self.surroundings[y][x] {
+						Some(ch) if x + char_width(ch) <= right => ch,
+						Some(_) => ' ',
+						None if x == left => ' ',
+						None => continue
+					};
+					out.queue(SetAttribute(Attribute::Dim))?
+						.queue(Print(ch))?
+						.queue(SetAttribute(Attribute::Reset))?;
+					continue;
+				}
+				// the second cell of a wide character is already covered by the character
+				let Some(ch) = cells.get(inner.0).copied().unwrap_or(Some(' ')) else {
+					continue;
+				};
+				if inner == self.cursor {
+					out.queue(SetAttribute(Attribute::Reverse))?;
+				}
+				if self.parcel.links.contains_key(&Self::char_at(&self.parcel.mask, inner)) {
+					out.queue(SetAttribute(Attribute::Underlined))?;
+				}
+				out.queue(Print(ch))?
+					.queue(SetAttribute(Attribute::Reset))?;
+			}
+		}
+		let status_row = (PLOT_HEIGHT + 2 * MARGIN_Y + 1) as u16;
+		let layer = match self.layer {
+			Layer::Art => "art",
+			Layer::Mask => "link mask"
+		};
+		let link = match self.link_under_cursor() {
+			Some((key, link)) => format!("'{}' -> {}", key, link),
+			None => "no link".to_string()
+		};
+		out.queue(cursor::MoveTo(0, status_row))?
+			.queue(Print(format!(
+				"{} {} | layer: {} | {},{} | {}",
+				self.parcel.location.x,
+				self.parcel.location.y,
+				layer,
+				self.cursor.0,
+				self.cursor.1,
+				link
+			)))?;
+		let start = self.brush.saturating_sub(8);
+		out.queue(cursor::MoveTo(0, status_row + 1))?.queue(Print("brush: "))?;
+		for (i, ch) in self.palette.iter().enumerate().skip(start).take(17) {
+			if i == self.brush {
+				out.queue(SetAttribute(Attribute::Reverse))?;
+			}
+			out.queue(Print(ch))?.queue(SetAttribute(Attribute::Reset))?;
+		}
+		out.queue(cursor::MoveTo(0, status_row + 2))?
+			.queue(Print("arrows: move  tab: art/mask  pgup/pgdn: brush  enter: paint  ctrl-l: link  ctrl-s: save  esc: quit"))?;
+		out.queue(cursor::MoveTo(0, status_row + 3))?;
+		match &self.prompt {
+			Some((key, link)) => out.queue(Print(format!("link for '{}': {}", key, link)))?,
+			None => out.queue(Print(&self.message))?
+		};
+		out.flush()
+	}
+}
+
+/// Put a character in a cell, replacing the wide characters that it overlaps with spaces
+fn set_cell(cells: &mut [Option<char>], x: usize, ch: char) {
+	let width = char_width(ch);
+	for cell in x..(x + width) {
+		if cells[cell].is_none() {
+			cells[cell - 1] = Some(' ');
+			cells[cell] = Some(' ');
+		} else if cells.get(cell + 1) == Some(&None) {
+			cells[cell + 1] = Some(' ');
+		}
+	}
+	cells[x] = Some(ch);
+	if width == 2 {
+		cells[x + 1] = None;
+	}
+}
+
+/// Run the editor in the terminal until the user quits, saving the parcel to the given path when asked
+pub fn run(mut editor: Editor, path: &Path) -> io::Result<()> {
+	let mut out = io::stdout();
+	terminal::enable_raw_mode()?;
+	out.queue(terminal::EnterAlternateScreen)?.queue(cursor::Hide)?;
+	let result = edit_loop(&mut editor, path, &mut out);
+	out.queue(cursor::Show)?.queue(terminal::LeaveAlternateScreen)?.flush()?;
+	terminal::disable_raw_mode()?;
+	result
+}
+
+fn edit_loop(editor: &mut Editor, path: &Path, out: &mut impl Write) -> io::Result<()> {
+	loop {
+		editor.draw(out)?;
+		let Event::Key(key) = event::read()? else {
+			continue;
+		};
+		if key.kind == KeyEventKind::Release {
+			continue;
+		}
+		match editor.handle_key(key) {
+			Action::Continue => {}
+			Action::Save => {
+				fs::write(path, editor.parcel.to_text())?;
+				editor.modified = false;
+				editor.message = format!("saved to {:?}", path);
+			}
+			Action::Quit => return Ok(())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{hashmap, owner::Owner};
+
+	fn press(editor: &mut Editor, code: KeyCode) -> Action {
+		editor.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+	}
+
+	fn type_text(editor: &mut Editor, text: &str) {
+		for ch in text.chars() {
+			press(editor, KeyCode::Char(ch));
+		}
+	}
+
+	#[test]
+	fn draw_on_both_layers() {
+		let parcel = Parcel::from_text("1 1\nhello", Owner::user("troido")).unwrap();
+		let mut editor = Editor::new(parcel, &Cadastre::empty(), Charset::default());
+		press(&mut editor, KeyCode::Down);
+		type_text(&mut editor, "hi");
+		assert_eq!(editor.parcel.art[1], format!("hi{}", " ".repeat(22)));
+		press(&mut editor, KeyCode::Tab);
+		press(&mut editor, KeyCode::Home);
+		press(&mut editor, KeyCode::Up);
+		type_text(&mut editor, "11\u{7}");
+		assert_eq!(editor.message, "'\u{7}' is not an allowed character");
+		assert_eq!(editor.parcel.mask[0], format!("11llo{}", " ".repeat(19)));
+		editor.cursor = (0, 0);
+		editor.handle_key(KeyEvent::new(KeyCode::Char('l'), KeyModifiers::CONTROL));
+		type_text(&mut editor, "https://tilde.town/~troido/");
+		press(&mut editor, KeyCode::Enter);
+		assert_eq!(editor.parcel.links, hashmap!{'1' => Link::new("https://tilde.town/~troido/")});
+		assert_eq!(editor.link_under_cursor(), Some(('1', "https://tilde.town/~troido/")));
+		assert!(editor.modified);
+	}
+
+	#[test]
+	fn draw_wide_characters() {
+		let parcel = Parcel::from_text("1 1\nabcd", Owner::user("troido")).unwrap();
+		let charset = Charset::from_chars("abcd漢字".chars());
+		let mut editor = Editor::new(parcel, &Cadastre::empty(), charset);
+		editor.cursor = (1, 0);
+		type_text(&mut editor, "漢");
+		assert_eq!(editor.parcel.art[0], format!("a漢d{}", " ".repeat(20)));
+		assert_eq!(editor.cursor, (3, 0));
+		editor.cursor = (2, 0);
+		type_text(&mut editor, "字");
+		assert_eq!(editor.parcel.art[0], format!("a 字{}", " ".repeat(20)));
+		editor.cursor = (23, 0);
+		type_text(&mut editor, "漢");
+		assert_eq!(editor.message, "'漢' is too wide for the last cell");
+	}
+
+	#[test]
+	fn ask_before_quitting_with_changes() {
+		let mut editor = Editor::new(Parcel::empty(Owner::user("troido"), Pos::new(0, 0)), &Cadastre::empty(), Charset::default());
+		assert_eq!(press(&mut editor, KeyCode::Esc), Action::Quit);
+		press(&mut editor, KeyCode::Enter);
+		assert_eq!(press(&mut editor, KeyCode::Esc), Action::Continue);
+		assert_eq!(press(&mut editor, KeyCode::Esc), Action::Quit);
+	}
+}
@@ -0,0 +1,154 @@
+
+/// Names for characters that are hard to type, used as `:name:` in parcel files that enable escapes
+pub const ALIASES: &[(&str, char)] = &[
+	("box_tl", '╔'),
+	("box_tr", '╗'),
+	("box_bl", '╚'),
+	("box_br", '╝'),
+	("box_h", '═'),
+	("box_v", '║'),
+	("box_l", '╠'),
+	("box_r", '╣'),
+	("box_t", '╦'),
+	("box_b", '╩'),
+	("line_tl", '┌'),
+	("line_tr", '┐'),
+	("line_bl", '└'),
+	("line_br", '┘'),
+	("line_h", '─'),
+	("line_v", '│'),
+	("line_l", '├'),
+	("line_r", '┤'),
+	("line_t", '┬'),
+	("line_b", '┴'),
+	("line_x", '┼'),
+	("heavy_tl", '┏'),
+	("heavy_tr", '┓'),
+	("heavy_bl", '┗'),
+	("heavy_br", '┛'),
+	("heavy_l", '┣'),
+	("heavy_r", '┫'),
+	("heavy_t", '┳'),
+	("heavy_b", '┻'),
+	("round_tl", '╭'),
+	("round_tr", '╮'),
+	("round_bl", '╰'),
+	("diag_up", '╱'),
+	("diag_down", '╲'),
+	("block", '█'),
+	("block_upper", '▀'),
+	("block_lower", '▄'),
+	("block_left", '▌'),
+	("block_right", '▐'),
+	("shade_light", '░'),
+	("shade_medium", '▒'),
+	("shade_dark", '▓'),
+	("braille_full", '⣿'),
+	("braille_blank", '⠀'),
+	("heart", '♥'),
+	("spade", '♠'),
+	("note", '♪'),
+	("notes", '♫'),
+	("star", '☆'),
+	("smiley", '☺'),
+	("flower", '❀'),
+	("bullet", '•'),
+	("ellipsis", '…'),
+	("infinity", '∞'),
+	("degree", '°'),
+	("pi", 'π'),
+	("approx", '≈'),
+	("times", '×'),
+	("arrow_left", '◄'),
+	("arrow_right", '►'),
+];
+
+/// Expand the escapes in a line of art or mask:
+/// - `\u{2554}` is the character with that hexadecimal code point
+/// - `:box_tl:` is the character with that name in the alias table
+/// - `\\` and `\:` are a literal backslash and colon
+///
+/// Everything that is not a valid escape is kept as it is
+pub fn expand(line: &str) -> String {
+	expand_with_columns(line).0
+}
+
+/// Expand the escapes in a line like `expand`, and also give the column in the original line where each expanded character starts
+/// This way problems with the expanded characters can be reported where the user wrote them
+pub fn expand_with_columns(line: &str) -> (String, Vec<usize>) {
+	let mut result = String::with_capacity(line.len());
+	let mut columns = Vec::with_capacity(line.len());
+	let mut column = 0;
+	let mut rest = line;
+	while let Some(ch) = rest.chars().next() {
+		let (expanded, len) = match ch {
+			'\\' => match rest[1..].chars().next() {
+				Some(escaped @ ('\\' | ':')) => (escaped, 2),
+				Some('u') => match code_point(&rest[2..]) {
+					Some((code_point, len)) => (code_point, 2 + len),
+					None => (ch, 1)
+				},
+				_ => (ch, 1)
+			},
+			':' => match alias(&rest[1..]) {
+				Some((aliased, len)) => (aliased, 1 + len),
+				None => (ch, 1)
+			},
+			_ => (ch, ch.len_utf8())
+		};
+		result.push(expanded);
+		columns.push(column);
+		column += rest[..len].chars().count();
+		rest = &rest[len..];
+	}
+	(result, columns)
+}
+
+/// A code point in braces at the start of the text, and the length of that part of the text
+fn code_point(text: &str) -> Option<(char, usize)> {
+	let (hex, _) = text.strip_prefix('{')?.split_once('}')?;
+	let ch = char::from_u32(u32::from_str_radix(hex, 16).ok()?)?;
+	Some((ch, hex.len() + 2))
+}
+
+/// An alias name followed by a colon at the start of the text, and the length of that part of the text
+fn alias(text: &str) -> Option<(char, usize)> {
+	let (name, _) = text.split_once(':')?;
+	let (_, ch) = ALIASES.iter().find(|(alias, _)| *alias == name)?;
+	Some((*ch, name.len() + 1))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::charset::Charset;
+
+	#[test]
+	fn expand_escapes() {
+		assert_eq!(expand(r"\u{2554}:box_h::box_tr:"), "╔═╗");
+		assert_eq!(expand(r"a\\b\:box_h: \:"), r"a\b:box_h: :");
+		assert_eq!(expand(r"\u{zz} \u{110000} ╔ \x"), r"\u{zz} \u{110000} ╔ \x");
+		assert_eq!(expand("time: 12:30 :nothing: :heart:"), "time: 12:30 :nothing: ♥");
+		assert_eq!(expand(r"ends with \"), r"ends with \");
+	}
+
+	#[test]
+	fn expand_escapes_with_columns() {
+		assert_eq!(expand_with_columns(r"a\u{2554}:box_h:b\\"), ("a╔═b\\".to_string(), vec![0, 1, 9, 16, 17]));
+		assert_eq!(expand_with_columns("╔:x:"), ("╔:x:".to_string(), vec![0, 1, 2, 3]));
+	}
+
+	#[test]
+	fn aliases_are_allowed_by_default() {
+		for (name, ch) in ALIASES {
+			assert!(Charset::default().contains(*ch), "{} stands for {:?}, which is not in the default character set", name, ch);
+		}
+	}
+
+	#[test]
+	fn aliases_are_unique() {
+		for (i, (name, _)) in ALIASES.iter().enumerate() {
+			assert!(!ALIASES[..i].iter().any(|(other, _)| other == name), "{} is defined twice", name);
+		}
+	}
+}
@@ -0,0 +1,93 @@
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::{
+	cadastre::Cadastre,
+	parcel::Parcel,
+	pos::Pos
+};
+
+/// The plots to write as parcel files: a single plot, or all plots of a user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+	Plot(Pos),
+	Owner(String)
+}
+
+impl ExportTarget {
+	/// A position when the vertical position is given, otherwise the name of a user
+	pub fn from_args(target: &str, y: Option<i64>) -> Result<Self, String> {
+		match y {
+			Some(y) => {
+				let x: i64 = target.parse().map_err(|_| "The horizontal position of the plot must be an integer".to_string())?;
+				Ok(Self::Plot(Pos::new(x, y)))
+			}
+			None => Ok(Self::Owner(target.to_string()))
+		}
+	}
+
+	/// The parcels in the town that this target refers to
+	pub fn select<'a>(&self, cadastre: &'a Cadastre) -> Vec<&'a Parcel> {
+		match self {
+			Self::Plot(pos) => cadastre.parcel(*pos).into_iter().collect(),
+			Self::Owner(name) => cadastre.find_by_owner(name)
+		}
+	}
+}
+
+impl fmt::Display for ExportTarget {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Plot(pos) => write!(f, "plot {} {}", pos.x, pos.y),
+			Self::Owner(name) => write!(f, "{}", name)
+		}
+	}
+}
+
+/// The file that a parcel is written to when no other file is given: x_y.prcl in the current directory
+pub fn default_path(parcel: &Parcel) -> PathBuf {
+	format!("{}_{}.prcl", parcel.location.x, parcel.location.y).into()
+}
+
+/// Write a new parcel file, refusing to overwrite an existing file unless forced
+/// When the file exists, the error has kind AlreadyExists
+pub fn write_parcel_file(path: &Path, text: &str, force: bool) -> io::Result<()> {
+	let mut file = fs::OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.create_new(!force)
+		.open(path)?;
+	file.write_all(text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Owner, safefile::tests::test_dir};
+
+	#[test]
+	fn select_export_targets() {
+		let town = Cadastre::empty()
+			.with_parcel(Parcel::empty(Owner::user("troido"), Pos::new(3, 4)))
+			.with_parcel(Parcel::empty(Owner::Shared(vec!["odiort".to_string(), "troido".to_string()]), Pos::new(5, 6)));
+		assert_eq!(ExportTarget::from_args("3", Some(4)), Ok(ExportTarget::Plot(Pos::new(3, 4))));
+		assert!(ExportTarget::from_args("troido", Some(4)).is_err());
+		assert_eq!(ExportTarget::Plot(Pos::new(3, 4)).select(&town).len(), 1);
+		assert_eq!(ExportTarget::Plot(Pos::new(4, 4)).select(&town).len(), 0);
+		assert_eq!(ExportTarget::from_args("troido", None).unwrap().select(&town).len(), 2);
+		assert_eq!(default_path(&Parcel::empty(Owner::Public, Pos::new(-1, 2))), PathBuf::from("-1_2.prcl"));
+	}
+
+	#[test]
+	fn refuse_to_overwrite_unless_forced() {
+		let path = test_dir("export").join("3_4.prcl");
+		write_parcel_file(&path, "first", false).unwrap();
+		assert_eq!(write_parcel_file(&path, "second", false).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+		assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+		write_parcel_file(&path, "third", true).unwrap();
+		assert_eq!(fs::read_to_string(&path).unwrap(), "third");
+	}
+}
@@ -0,0 +1,431 @@
+
+use std::io::Cursor;
+use crate::{
+	charset::Charset,
+	parcel::{self, Replacement, PLOT_WIDTH, PLOT_HEIGHT}
+};
+
+/// Characters from light to dense, used to draw images
+const RAMP: &str = " .:-=+*#%@";
+/// Images larger than this many bytes after decoding are refused
+const MAX_IMAGE_BYTES: usize = 1 << 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ImportFormat {
+	/// plain text
+	Text,
+	/// text with ANSI escape codes
+	Ansi,
+	/// PNG image
+	Png,
+	/// PPM or PGM image
+	Ppm
+}
+
+impl ImportFormat {
+	/// Guess the format from the file extension, assuming plain text for unknown extensions
+	pub fn from_path(path: &std::path::Path) -> Self {
+		match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+			Some("ans") | Some("ansi") => Self::Ansi,
+			Some("png") => Self::Png,
+			Some("ppm") | Some("pgm") | Some("pnm") => Self::Ppm,
+			_ => Self::Text
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Fit {
+	/// cut off everything that doesn't fit; images use one pixel per character horizontally and two vertically
+	Crop,
+	/// shrink the source until it fits
+	Scale
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Anchor {
+	TopLeft,
+	Top,
+	TopRight,
+	Left,
+	Center,
+	Right,
+	BottomLeft,
+	Bottom,
+	BottomRight
+}
+
+/// Convert the contents of a file into the art of a plot
+/// The result is normalised the same way as the art in parcel files
+/// Besides the art this returns the characters that were replaced, as they would be in a parcel file with only a position line before the art
+pub fn import(source: &[u8], format: ImportFormat, fit: Fit, anchor: Anchor, invert: bool, charset: &Charset) -> Result<(Vec<String>, Vec<Replacement>), String> {
+	let grid = match format {
+		ImportFormat::Text => text_grid(&String::from_utf8_lossy(source)),
+		ImportFormat::Ansi => text_grid(&strip_ansi(&String::from_utf8_lossy(source))),
+		ImportFormat::Png => read_png(source)?.to_grid(fit, invert, charset),
+		ImportFormat::Ppm => read_ppm(source)?.to_grid(fit, invert, charset)
+	};
+	let grid = match fit {
+		Fit::Crop => grid,
+		Fit::Scale => scale(grid)
+	};
+	let mut replacements = Vec::new();
+	let art = place(&grid, anchor)
+		.iter()
+		.enumerate()
+		.map(|(y, row)| parcel::process_plot_line(&String::from_iter(row), PLOT_WIDTH, charset, y + 1, &mut replacements))
+		.collect();
+	Ok((art, replacements))
+}
+
+/// Split text into rows of characters, expanding tabs and cropping away the empty border around the content
+fn text_grid(text: &str) -> Vec<Vec<char>> {
+	let rows: Vec<Vec<char>> = text.lines()
+		.map(|line| {
+			let mut row = Vec::new();
+			for ch in line.chars() {
+				if ch == '\t' {
+					row.extend(std::iter::repeat_n(' ', 8 - row.len() % 8));
+				} else {
+					row.push(ch);
+				}
+			}
+			row
+		})
+		.collect();
+	let is_blank = |ch: &char| ch.is_whitespace();
+	let Some(top) = rows.iter().position(|row| !row.iter().all(is_blank)) else {
+		return Vec::new();
+	};
+	let bottom = rows.iter().rposition(|row| !row.iter().all(is_blank)).unwrap_or(top);
+	let content = &rows[top..=bottom];
+	let left = content.iter().filter_map(|row| row.iter().position(|ch| !is_blank(ch))).min().unwrap_or(0);
+	content.iter()
+		.map(|row| {
+			let end = row.iter().rposition(|ch| !is_blank(ch)).map_or(0, |i| i + 1);
+			row.get(left..end).unwrap_or(&[]).to_vec()
+		})
+		.collect()
+}
+
+/// Remove terminal escape codes from ANSI art
+/// Cursor forward codes are replaced by spaces, since ANSI art often uses them instead of spaces
+fn strip_ansi(text: &str) -> String {
+	// a SUB character marks the end of the art, and the start of the SAUCE metadata
+	let text = text.split('\x1a').next().unwrap_or("");
+	let mut result = String::with_capacity(text.len());
+	let mut chars = text.chars().peekable();
+	while let Some(ch) = chars.next() {
+		match ch {
+			'\x1b' => match chars.next() {
+				Some('[') => {
+					let mut params = String::new();
+					for ch in chars.by_ref() {
+						if ('@'..='~').contains(&ch) {
+							if ch == 'C' {
+								let count = params.parse::<usize>().unwrap_or(1);
+								result.extend(std::iter::repeat_n(' ', count));
+							}
+							break;
+						}
+						params.push(ch);
+					}
+				}
+				Some(']') => {
+					// operating system commands end with BEL or with ESC \
+					while let Some(ch) = chars.next() {
+						if ch == '\x07' || (ch == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+							break;
+						}
+					}
+				}
+				_ => {}
+			},
+			'\n' | '\t' => result.push(ch),
+			ch if ch.is_control() => {}
+			ch => result.push(ch)
+		}
+	}
+	result
+}
+
+/// Shrink the grid by skipping rows and columns until it fits in a plot
+fn scale(grid: Vec<Vec<char>>) -> Vec<Vec<char>> {
+	let width = grid.iter().map(Vec::len).max().unwrap_or(0);
+	let height = grid.len();
+	let factor = (width as f64 / PLOT_WIDTH as f64).max(height as f64 / PLOT_HEIGHT as f64);
+	if factor <= 1.0 {
+		return grid;
+	}
+	let new_width = (width as f64 / factor).round() as usize;
+	let new_height = (height as f64 / factor).round() as usize;
+	(0..new_height)
+		.map(|y| {
+			let row = &grid[(y as f64 * factor) as usize];
+			(0..new_width)
+				.map(|x| row.get((x as f64 * factor) as usize).copied().unwrap_or(' '))
+				.collect()
+		})
+		.collect()
+}
+
+/// Put the grid in a plot according to the anchor, cutting off everything that falls outside
+fn place(grid: &[Vec<char>], anchor: Anchor) -> Vec<Vec<char>> {
+	let width = grid.iter().map(Vec::len).max().unwrap_or(0) as i64;
+	let height = grid.len() as i64;
+	// how far to the right and to the bottom, in halves
+	let (right, down) = match anchor {
+		Anchor::TopLeft => (0, 0),
+		Anchor::Top => (1, 0),
+		Anchor::TopRight => (2, 0),
+		Anchor::Left => (0, 1),
+		Anchor::Center => (1, 1),
+		Anchor::Right => (2, 1),
+		Anchor::BottomLeft => (0, 2),
+		Anchor::Bottom => (1, 2),
+		Anchor::BottomRight => (2, 2)
+	};
+	let offset_x = (PLOT_WIDTH as i64 - width) * right / 2;
+	let offset_y = (PLOT_HEIGHT as i64 - height) * down / 2;
+	(0..PLOT_HEIGHT as i64)
+		.map(|y| {
+			(0..PLOT_WIDTH as i64)
+				.map(|x| {
+					usize::try_from(y - offset_y).ok()
+						.and_then(|sy| grid.get(sy))
+						.and_then(|row| usize::try_from(x - offset_x).ok().and_then(|sx| row.get(sx)))
+						.copied()
+						.unwrap_or(' ')
+				})
+				.collect()
+		})
+		.collect()
+}
+
+/// An image as the brightness and opacity of each pixel, both between 0 and 1
+#[derive(Debug, Clone, PartialEq)]
+struct Image {
+	width: usize,
+	height: usize,
+	pixels: Vec<(f64, f64)>
+}
+
+impl Image {
+	/// Turn the image into characters, where each character covers a block of pixels twice as high as it is wide
+	fn to_grid(&self, fit: Fit, invert: bool, charset: &Charset) -> Vec<Vec<char>> {
+		let ramp: Vec<char> = RAMP.chars().filter(|ch| charset.contains(*ch)).collect();
+		let block = match fit {
+			Fit::Crop => 1.0,
+			Fit::Scale => (self.width as f64 / PLOT_WIDTH as f64)
+				.max(self.height as f64 / (2 * PLOT_HEIGHT) as f64)
+				.max(1.0)
+		};
+		let columns = (self.width as f64 / block).ceil() as usize;
+		let rows = (self.height as f64 / (2.0 * block)).ceil() as usize;
+		(0..rows)
+			.map(|row| {
+				(0..columns)
+					.map(|column| {
+						let ink = self.average(
+							span(column, block, self.width),
+							span(row, 2.0 * block, self.height),
+							invert
+						);
+						ramp[((ink * (ramp.len() - 1) as f64).round() as usize).min(ramp.len() - 1)]
+					})
+					.collect()
+			})
+			.collect()
+	}
+
+	/// How much ink a block of pixels needs: the average brightness (or darkness when inverted), weighted by opacity
+	fn average(&self, xs: std::ops::Range<usize>, ys: std::ops::Range<usize>, invert: bool) -> f64 {
+		let mut total = 0.0;
+		let mut count = 0;
+		for y in ys {
+			for x in xs.clone() {
+				let (brightness, alpha) = self.pixels[y * self.width + x];
+				total += if invert { 1.0 - brightness } else { brightness } * alpha;
+				count += 1;
+			}
+		}
+		if count == 0 { 0.0 } else { total / count as f64 }
+	}
+}
+
+/// The pixels covered by the nth block of the given size, with at least one pixel
+fn span(n: usize, size: f64, max: usize) -> std::ops::Range<usize> {
+	let start = ((n as f64 * size) as usize).min(max - 1);
+	let end = (((n + 1) as f64 * size) as usize).clamp(start + 1, max);
+	start..end
+}
+
+fn luminance(r: f64, g: f64, b: f64) -> f64 {
+	0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+fn read_png(data: &[u8]) -> Result<Image, String> {
+	let mut decoder = png::Decoder::new_with_limits(Cursor::new(data), png::Limits { bytes: MAX_IMAGE_BYTES });
+	decoder.set_transformations(png::Transformations::normalize_to_color8());
+	let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+	let mut buf = vec![0; reader.output_buffer_size().ok_or("image is too large")?];
+	let info = reader.next_frame(&mut buf).map_err(|err| err.to_string())?;
+	let (width, height) = (info.width as usize, info.height as usize);
+	let channels = info.color_type.samples();
+	let pixels = (0..height)
+		.flat_map(|y| (0..width).map(move |x| (y, x)))
+		.map(|(y, x)| {
+			let start = y * info.line_size + x * channels;
+			let sample = |i: usize| buf[start + i] as f64 / 255.0;
+			match info.color_type {
+				png::ColorType::Grayscale => (sample(0), 1.0),
+				png::ColorType::GrayscaleAlpha => (sample(0), sample(1)),
+				png::ColorType::Rgb => (luminance(sample(0), sample(1), sample(2)), 1.0),
+				png::ColorType::Rgba => (luminance(sample(0), sample(1), sample(2)), sample(3)),
+				// indexed images are expanded to rgb by the transformations
+				png::ColorType::Indexed => (0.0, 0.0)
+			}
+		})
+		.collect();
+	Ok(Image { width, height, pixels })
+}
+
+/// Read a binary or plain PPM (colour) or PGM (greyscale) image
+fn read_ppm(data: &[u8]) -> Result<Image, String> {
+	let mut pos = 0;
+	let mut token = || -> Result<String, String> {
+		loop {
+			match data.get(pos) {
+				Some(b'#') => while data.get(pos).is_some_and(|b| *b != b'\n') { pos += 1 },
+				Some(b) if b.is_ascii_whitespace() => pos += 1,
+				Some(_) => break,
+				None => return Err("unexpected end of image header".to_string())
+			}
+		}
+		let start = pos;
+		while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+			pos += 1;
+		}
+		Ok(String::from_utf8_lossy(&data[start..pos]).to_string())
+	};
+	let magic = token()?;
+	let number = |txt: String| txt.parse::<usize>().map_err(|_| format!("invalid number in image header: {}", txt));
+	let width = number(token()?)?;
+	let height = number(token()?)?;
+	let maxval = number(token()?)?;
+	if width == 0 || height == 0 {
+		return Err("image is empty".to_string());
+	}
+	if maxval == 0 || maxval > 65535 {
+		return Err(format!("invalid maximum value {}", maxval));
+	}
+	let channels = match magic.as_str() {
+		"P2" | "P5" => 1,
+		"P3" | "P6" => 3,
+		_ => return Err(format!("unsupported image type {}", magic))
+	};
+	let count = width.checked_mul(height).and_then(|n| n.checked_mul(channels))
+		.filter(|n| *n <= MAX_IMAGE_BYTES)
+		.ok_or("image is too large")?;
+	let samples: Vec<f64> = if magic == "P2" || magic == "P3" {
+		(0..count).map(|_| token().and_then(number).map(|n| n as f64 / maxval as f64)).collect::<Result<_, _>>()?
+	} else {
+		// a single whitespace character separates the header from the binary data
+		let body = data.get(pos + 1..).unwrap_or(&[]);
+		let size = if maxval > 255 { 2 } else { 1 };
+		if body.len() < count * size {
+			return Err("image data is too short".to_string());
+		}
+		body.chunks(size)
+			.take(count)
+			.map(|chunk| chunk.iter().fold(0, |n, b| n * 256 + *b as usize) as f64 / maxval as f64)
+			.collect()
+	};
+	let pixels = samples.chunks(channels)
+		.map(|pixel| match pixel {
+			[grey] => (*grey, 1.0),
+			[r, g, b] => (luminance(*r, *g, *b), 1.0),
+			_ => unreachable!()
+		})
+		.collect();
+	Ok(Image { width, height, pixels })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn import_art(source: &[u8], format: ImportFormat, fit: Fit, anchor: Anchor, invert: bool) -> Vec<String> {
+		import(source, format, fit, anchor, invert, &Charset::default()).unwrap().0
+	}
+
+	fn plot(rows: &[&str]) -> Vec<String> {
+		(0..PLOT_HEIGHT).map(|y| format!("{:<24}", rows.get(y).unwrap_or(&""))).collect()
+	}
+
+	#[test]
+	fn import_text_with_anchor() {
+		let source = b"\n\n    hello\n      world\n\n";
+		assert_eq!(import_art(source, ImportFormat::Text, Fit::Crop, Anchor::TopLeft, false), plot(&["hello", "  world"]));
+		let centered = import_art(source, ImportFormat::Text, Fit::Crop, Anchor::Center, false);
+		assert_eq!(centered[5], format!("{}hello{}", " ".repeat(8), " ".repeat(11)));
+		assert_eq!(centered[6], format!("{}  world{}", " ".repeat(8), " ".repeat(9)));
+		let bottom_right = import_art(source, ImportFormat::Text, Fit::Crop, Anchor::BottomRight, false);
+		assert_eq!(bottom_right[11], format!("{}  world", " ".repeat(17)));
+	}
+
+	#[test]
+	fn crop_or_scale_large_text() {
+		let source = (0..24).map(|y| format!("{}\n", if y % 2 == 0 { "ab".repeat(24) } else { "-".repeat(48) })).collect::<String>();
+		let cropped = import_art(source.as_bytes(), ImportFormat::Text, Fit::Crop, Anchor::TopLeft, false);
+		assert_eq!(cropped[0], "ab".repeat(12));
+		assert_eq!(cropped[11], "-".repeat(24));
+		let scaled = import_art(source.as_bytes(), ImportFormat::Text, Fit::Scale, Anchor::TopLeft, false);
+		assert_eq!(scaled, vec!["a".repeat(24); 12]);
+	}
+
+	#[test]
+	fn report_replaced_characters() {
+		let (art, replacements) = import("\n a\n 👻b".as_bytes(), ImportFormat::Text, Fit::Crop, Anchor::TopLeft, false, &Charset::default()).unwrap();
+		assert_eq!(art[1], format!("?b{}", " ".repeat(22)));
+		assert_eq!(replacements, vec![Replacement { row: 2, column: 0, ch: '👻', reason: parcel::ReplaceReason::NotAllowed }]);
+	}
+
+	#[test]
+	fn strip_ansi_codes() {
+		let source = "\x1b[0;1;31mred\x1b[0m\x1b[3Cplain\x1b]0;title\x07\r\n\x1b[Cx\x1aSAUCE00 metadata";
+		assert_eq!(strip_ansi(source), "red   plain\n x");
+		let parcel = import_art(source.as_bytes(), ImportFormat::Ansi, Fit::Crop, Anchor::TopLeft, false);
+		assert_eq!(parcel[..2], plot(&["red   plain", " x"])[..2]);
+	}
+
+	#[test]
+	fn import_ppm_by_brightness() {
+		let source = b"P3\n# a comment\n4 2\n255\n0 0 0  255 255 255  255 0 0  0 0 0\n0 0 0  255 255 255  255 0 0  0 0 0\n";
+		let parcel = import_art(source, ImportFormat::Ppm, Fit::Crop, Anchor::TopLeft, false);
+		assert_eq!(parcel[0], format!(" @: {}", " ".repeat(20)));
+		let inverted = import_art(source, ImportFormat::Ppm, Fit::Crop, Anchor::TopLeft, true);
+		assert_eq!(inverted[0], format!("@ #@{}", " ".repeat(20)));
+		let binary = [b"P5 2 2 255\n".as_slice(), &[0, 255, 0, 255]].concat();
+		assert_eq!(import_art(&binary, ImportFormat::Ppm, Fit::Crop, Anchor::TopLeft, false)[0], format!(" @{}", " ".repeat(22)));
+		assert!(read_ppm(b"P6 2 2 255\n\0\0").is_err());
+		assert!(read_ppm(b"P6 0 0 255\n").is_err());
+	}
+
+	#[test]
+	fn import_png_with_transparency() {
+		let mut data = Vec::new();
+		{
+			let mut encoder = png::Encoder::new(&mut data, 96, 96);
+			encoder.set_color(png::ColorType::Rgba);
+			let mut writer = encoder.write_header().unwrap();
+			let pixels: Vec<u8> = (0..96).flat_map(|_| (0..96).flat_map(|x| if x < 48 { [255, 255, 255, 255] } else { [255, 255, 255, 0] })).collect();
+			writer.write_image_data(&pixels).unwrap();
+		}
+		let parcel = import_art(&data, ImportFormat::Png, Fit::Scale, Anchor::TopLeft, false);
+		assert_eq!(parcel, vec![format!("{}{}", "@".repeat(12), " ".repeat(12)); 12]);
+	}
+}
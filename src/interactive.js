@@ -0,0 +1,140 @@
+// Extras for the interactive html render: information about the plot under the mouse, a minimap and zoom buttons
+// The page works without this script; it only adds to it
+(function () {
+	"use strict";
+	var data = JSON.parse(document.getElementById("cadastre-data").textContent);
+	var map = document.getElementById("0,0").parentNode;
+	var plots = {};
+	data.plots.forEach(function (plot) {
+		plots[plot.x + "," + plot.y] = plot;
+	});
+
+	// the size of a plot on the page, from the width of a single character and the height of the whole map
+	var plotWidth, plotHeight;
+	function measure() {
+		var style = getComputedStyle(map);
+		var height = map.getBoundingClientRect().height - parseFloat(style.paddingTop) - parseFloat(style.paddingBottom);
+		plotHeight = height / data.height;
+		var probe = document.createElement("span");
+		probe.textContent = "x";
+		map.appendChild(probe);
+		plotWidth = probe.getBoundingClientRect().width * data.plotWidth;
+		map.removeChild(probe);
+	}
+
+	function mapOrigin() {
+		var rect = map.getBoundingClientRect();
+		var style = getComputedStyle(map);
+		return {
+			x: rect.left + window.scrollX + parseFloat(style.paddingLeft),
+			y: rect.top + window.scrollY + parseFloat(style.paddingTop)
+		};
+	}
+
+	// hovering over the map shows who owns the plot under the mouse
+	var info = document.createElement("div");
+	info.className = "hoverinfo";
+	info.hidden = true;
+	document.body.appendChild(info);
+	map.addEventListener("mousemove", function (event) {
+		var origin = mapOrigin();
+		var x = Math.floor((event.pageX - origin.x) / plotWidth);
+		var y = Math.floor((event.pageY - origin.y) / plotHeight);
+		if (x < 0 || y < 0 || x >= data.width || y >= data.height) {
+			info.hidden = true;
+			return;
+		}
+		var plot = plots[x + "," + y];
+		var text = x + "," + y;
+		if (!plot) {
+			text += ": unclaimed";
+		} else if (plot.owners.length) {
+			text += ": " + plot.owners.map(function (name) { return "~" + name; }).join(", ");
+		}
+		if (plot && plot.title) {
+			text += "\n" + plot.title;
+		}
+		info.textContent = text;
+		info.style.left = (event.clientX + 12) + "px";
+		info.style.top = (event.clientY + 12) + "px";
+		info.hidden = false;
+	});
+	map.addEventListener("mouseleave", function () {
+		info.hidden = true;
+	});
+
+	// the minimap shows the claimed plots and the part of the town that is in view; clicking it goes there
+	var controls = document.createElement("div");
+	controls.className = "mapcontrols";
+	var scale = 4;
+	var minimap = document.createElement("canvas");
+	minimap.width = data.width * scale;
+	minimap.height = data.height * scale;
+	minimap.title = "Minimap";
+	function drawMinimap() {
+		var context = minimap.getContext("2d");
+		var colour = getComputedStyle(document.body).color;
+		context.clearRect(0, 0, minimap.width, minimap.height);
+		context.globalAlpha = 0.5;
+		context.fillStyle = colour;
+		data.plots.forEach(function (plot) {
+			context.fillRect(plot.x * scale, plot.y * scale, scale - 1, scale - 1);
+		});
+		var origin = mapOrigin();
+		context.globalAlpha = 1;
+		context.strokeStyle = colour;
+		context.strokeRect(
+			(window.scrollX - origin.x) / plotWidth * scale,
+			(window.scrollY - origin.y) / plotHeight * scale,
+			window.innerWidth / plotWidth * scale,
+			window.innerHeight / plotHeight * scale
+		);
+	}
+	minimap.addEventListener("click", function (event) {
+		var rect = minimap.getBoundingClientRect();
+		var origin = mapOrigin();
+		window.scrollTo({
+			left: origin.x + (event.clientX - rect.left) / scale * plotWidth - window.innerWidth / 2,
+			top: origin.y + (event.clientY - rect.top) / scale * plotHeight - window.innerHeight / 2
+		});
+	});
+
+	// zooming changes the font size of the map, keeping the same plot in the center of the view
+	var zoom = 1;
+	function setZoom(newZoom) {
+		var origin = mapOrigin();
+		var centerX = (window.scrollX + window.innerWidth / 2 - origin.x) / plotWidth;
+		var centerY = (window.scrollY + window.innerHeight / 2 - origin.y) / plotHeight;
+		zoom = Math.min(4, Math.max(0.25, newZoom));
+		map.style.fontSize = (zoom * 100) + "%";
+		measure();
+		origin = mapOrigin();
+		window.scrollTo({
+			left: origin.x + centerX * plotWidth - window.innerWidth / 2,
+			top: origin.y + centerY * plotHeight - window.innerHeight / 2,
+			behavior: "instant"
+		});
+		drawMinimap();
+	}
+	[["+", "Zoom in", 1.25], ["−", "Zoom out", 0.8], ["1:1", "Reset zoom", 0]].forEach(function (button) {
+		var element = document.createElement("button");
+		element.type = "button";
+		element.textContent = button[0];
+		element.title = button[1];
+		element.addEventListener("click", function () {
+			setZoom(button[2] ? zoom * button[2] : 1);
+		});
+		controls.appendChild(element);
+	});
+	controls.appendChild(document.createElement("br"));
+	controls.appendChild(minimap);
+	document.body.appendChild(controls);
+
+	measure();
+	drawMinimap();
+	window.addEventListener("scroll", drawMinimap, {passive: true});
+	window.addEventListener("resize", function () {
+		measure();
+		drawMinimap();
+	});
+})();
@@ -0,0 +1,60 @@
+//! Cadastre is a collaborative art project where all users make their own plot of ascii art.
+//! This crate stitches the art together to create a landscape.
+//!
+//! The main parts are:
+//! - [`Parcel`]: a single plot of art, parsed from the parcel text format with [`Parcel::from_text`]
+//! - [`Cadastre`]: the whole town, built from parcels with [`Cadastre::build`] and rendered with [`Cadastre::render_text`] and [`Cadastre::render_html`]
+//! - [`update`]: reading all parcels from the admin files, home dirs and public directories, as the `cadastrs update` command does
+//!
+//! The command line interface and the parts that need extra dependencies are behind features, which are all enabled by default:
+//! `cli` for the [`config`] and [`commands`] of the `cadastrs` command, `editor` for the terminal editor, `import` for converting images and `linkcheck` for checking links
+//!
+//! ```
+//! use cadastrs::{Cadastre, BuildRules, Parcel, Owner, Pos, Report};
+//!
+//! let parcel = Parcel::from_text("2 3\n  hello", Owner::user("troido")).unwrap();
+//! let mut report = Report::new();
+//! let town = Cadastre::build(&Cadastre::empty(), vec![parcel].into_iter(), &BuildRules::default(), &mut report);
+//! assert_eq!(town.owner_of(Pos::new(2, 3)), Some(Owner::user("troido")));
+//!
+//! let mut text = String::new();
+//! town.render_text(4, 4, |txt| text.push_str(txt));
+//! ```
+
+pub mod background;
+pub mod cadastre;
+pub mod charset;
+pub mod claim;
+#[cfg(feature = "cli")]
+pub mod commands;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "editor")]
+pub mod editor;
+pub mod escape;
+pub mod export;
+#[cfg(feature = "import")]
+pub mod import;
+pub mod link;
+#[cfg(feature = "linkcheck")]
+pub mod linkcheck;
+pub mod owner;
+pub mod parcel;
+pub mod pos;
+pub mod preview;
+pub mod report;
+pub mod safefile;
+pub mod timeout;
+pub mod transfer;
+pub mod update;
+mod privsep;
+mod util;
+mod strutil;
+
+pub use cadastre::{Cadastre, BuildRules, HistoryEvent, HtmlOptions};
+pub use owner::Owner;
+pub use charset::Charset;
+pub use link::Link;
+pub use parcel::{Parcel, HtmlLineOptions, ParseError, ParseErrorKind, Replacement, PLOT_WIDTH, PLOT_HEIGHT};
+pub use pos::Pos;
+pub use report::{Report, ReportEntry, ReportKind};
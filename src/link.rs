@@ -0,0 +1,163 @@
+
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+/// The html attributes that a link can have besides its title
+/// Other attributes are not allowed since they could change the rest of the page
+pub const LINK_ATTRIBUTES: &[&str] = &["rel", "hreflang", "target"];
+
+/// Where a key in the mask of a parcel links to, and how the link is described
+/// In the json a link without title or attributes is just its url, which is how all links were stored before they could have those
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from="LinkJson", into="LinkJson")]
+pub struct Link {
+	pub url: String,
+	/// Where the link goes, shown when hovering over it
+	pub title: Option<String>,
+	/// Extra html attributes, like 'rel=me'
+	pub attributes: BTreeMap<String, String>
+}
+
+impl Link {
+	pub fn new(url: &str) -> Self {
+		Self { url: url.to_string(), title: None, attributes: BTreeMap::new() }
+	}
+
+	/// Parse the part of a link line after the key: the url, optionally followed by a quoted title and attributes
+	/// For example: 'https://tilde.town/~troido/ "My homepage" rel=me'
+	/// If what follows the first word is not a title and attributes, then the whole text is the url, like before links could have titles
+	pub fn parse(text: &str) -> Self {
+		let text = text.trim();
+		if let Some((url, rest)) = crate::strutil::split_once_whitespace(text) {
+			if let Some((title, attributes)) = parse_details(rest) {
+				return Self { url: url.to_string(), title, attributes };
+			}
+		}
+		Self::new(text)
+	}
+
+	/// Write the link in the form that parse reads
+	pub fn to_text(&self) -> String {
+		let mut text = self.url.clone();
+		if let Some(title) = &self.title {
+			text.push_str(&format!(" \"{}\"", title.replace('\\', "\\\\").replace('"', "\\\"")));
+		}
+		for (name, value) in &self.attributes {
+			text.push_str(&format!(" {}={}", name, value));
+		}
+		text
+	}
+
+	/// What the link is called: the title, or otherwise the url
+	pub fn name(&self) -> &str {
+		self.title.as_deref().unwrap_or(&self.url)
+	}
+}
+
+/// The title and attributes after the url, or None if the text is something else
+fn parse_details(text: &str) -> Option<(Option<String>, BTreeMap<String, String>)> {
+	let mut rest = text.trim_start();
+	let mut title = None;
+	if let Some(quoted) = rest.strip_prefix('"') {
+		// the title ends at the next unescaped quote; a backslash escapes a quote or another backslash
+		let mut unquoted = String::new();
+		let mut chars = quoted.char_indices();
+		loop {
+			match chars.next()? {
+				(i, '"') => {
+					rest = &quoted[i+1..];
+					break;
+				}
+				(_, '\\') => unquoted.push(chars.next()?.1),
+				(_, ch) => unquoted.push(ch)
+			}
+		}
+		if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+			return None;
+		}
+		title = Some(unquoted);
+	}
+	let mut attributes = BTreeMap::new();
+	for entry in rest.split_whitespace() {
+		let (name, value) = entry.split_once('=')?;
+		if !LINK_ATTRIBUTES.contains(&name) || value.is_empty() {
+			return None;
+		}
+		attributes.insert(name.to_string(), value.to_string());
+	}
+	Some((title, attributes))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LinkJson {
+	Url(String),
+	Full {
+		url: String,
+		#[serde(default, skip_serializing_if="Option::is_none")]
+		title: Option<String>,
+		#[serde(default, skip_serializing_if="BTreeMap::is_empty")]
+		attributes: BTreeMap<String, String>
+	}
+}
+
+impl From<LinkJson> for Link {
+	fn from(json: LinkJson) -> Self {
+		match json {
+			LinkJson::Url(url) => Self::new(&url),
+			LinkJson::Full { url, title, attributes } => Self {
+				url,
+				title,
+				// the json can be edited by hand, so it gets the same limits as a parcel file
+				attributes: attributes.into_iter()
+					.filter(|(name, value)| LINK_ATTRIBUTES.contains(&name.as_str()) && !value.is_empty())
+					.collect()
+			}
+		}
+	}
+}
+
+impl From<Link> for LinkJson {
+	fn from(link: Link) -> Self {
+		if link.title.is_none() && link.attributes.is_empty() {
+			Self::Url(link.url)
+		} else {
+			Self::Full { url: link.url, title: link.title, attributes: link.attributes }
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_links() {
+		assert_eq!(Link::parse("https://example.com"), Link::new("https://example.com"));
+		let link = Link::parse(r#"https://example.com  "My \"blog\" \\o/"  rel=me target=_blank"#);
+		assert_eq!(link.url, "https://example.com");
+		assert_eq!(link.title.as_deref(), Some(r#"My "blog" \o/"#));
+		assert_eq!(link.attributes["rel"], "me");
+		assert_eq!(link.attributes["target"], "_blank");
+		assert_eq!(Link::parse(&link.to_text()), link);
+		assert_eq!(Link::parse("https://example.com rel=me").title, None);
+		// anything else after the url stays part of it, like it used to be
+		assert_eq!(Link::parse("javascript:alert(\"hi\") "), Link::new("javascript:alert(\"hi\")"));
+		assert_eq!(Link::parse("a \"b\"c"), Link::new("a \"b\"c"));
+		assert_eq!(Link::parse("a \"b"), Link::new("a \"b"));
+		assert_eq!(Link::parse("a onclick=alert(1)"), Link::new("a onclick=alert(1)"));
+	}
+
+	#[test]
+	fn link_json() {
+		let link: Link = serde_json::from_str("\"https://example.com\"").unwrap();
+		assert_eq!(link, Link::new("https://example.com"));
+		assert_eq!(serde_json::to_string(&link).unwrap(), "\"https://example.com\"");
+		let link = Link::parse("https://example.com \"Example\"");
+		let json = serde_json::to_string(&link).unwrap();
+		assert_eq!(json, r#"{"url":"https://example.com","title":"Example"}"#);
+		assert_eq!(serde_json::from_str::<Link>(&json).unwrap(), link);
+		let link: Link = serde_json::from_str(r#"{"url":"https://example.com","attributes":{"rel":"me","onclick":"alert(1)","target":""}}"#).unwrap();
+		assert_eq!(link.attributes, BTreeMap::from([("rel".to_string(), "me".to_string())]));
+	}
+}
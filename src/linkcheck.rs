@@ -0,0 +1,320 @@
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use ureq::unversioned::{
+	resolver::{DefaultResolver, ResolvedSocketAddrs, Resolver},
+	transport::{DefaultConnector, NextTimeout}
+};
+use crate::{
+	cadastre::Cadastre,
+	privsep,
+	report::{Report, ReportKind},
+	safefile::{self, FileError},
+	timeout::Limits
+};
+
+/// The result of checking a single link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkStatus {
+	Ok,
+	Broken(String),
+	/// The link is not local and http links are not checked, it uses a scheme that can't be checked,
+	/// or it points to a local file that update would refuse to read, which doesn't make the page itself dead
+	Unchecked
+}
+
+/// Finds links in the town that don't go anywhere
+/// Urls of pages in the home dirs are checked by looking for the file, other urls only when http checks are enabled
+pub struct LinkChecker {
+	/// Url patterns like 'https://tilde.town/~{user}/' for pages that are served from the home dirs
+	pub local_urls: Vec<String>,
+	/// The directory containing the home dirs
+	pub homedirs: PathBuf,
+	/// The directory within a home dir that the local urls of that user point to
+	pub public_dir_in_home: PathBuf,
+	/// How long looking for a local file can take
+	pub limits: Limits,
+	/// Look for local files as the owner of the home dir, so links can't reveal files the owner can't read
+	pub drop_privileges: bool,
+	/// The agent used for HEAD requests, if http links are checked
+	pub http: Option<ureq::Agent>
+}
+
+impl LinkChecker {
+	/// An agent for HEAD requests that gives up after the timeout
+	/// It only connects to public addresses, so links can't be used to probe the internal network, also not through redirects
+	pub fn http_agent(timeout: Duration) -> ureq::Agent {
+		let config = ureq::Agent::config_builder()
+			.timeout_global(Some(timeout))
+			.http_status_as_error(false)
+			// a proxy would be resolved instead of the host in the link
+			.proxy(None)
+			.build();
+		ureq::Agent::with_parts(config, DefaultConnector::default(), PublicResolver::default())
+	}
+
+	pub fn check(&self, url: &str) -> LinkStatus {
+		if let Some(local) = self.local_path(url) {
+			return match local {
+				Ok(path) => self.check_file(path),
+				Err(err) => LinkStatus::Broken(err)
+			};
+		}
+		match &self.http {
+			Some(agent) if url.starts_with("http://") || url.starts_with("https://") => check_http(agent, url),
+			_ => LinkStatus::Unchecked
+		}
+	}
+
+	/// The file that a local url points to, or None if the url is not local
+	fn local_path(&self, url: &str) -> Option<Result<PathBuf, String>> {
+		self.local_urls.iter().find_map(|pattern| {
+			let (prefix, suffix) = pattern.split_once("{user}")?;
+			let rest = url.strip_prefix(prefix)?;
+			let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+			let (user, rest) = rest.split_at(end);
+			if user.is_empty() {
+				return None;
+			}
+			// the user is joined to the homedirs path, so it must not be able to go anywhere else
+			if !matches!(Path::new(user).components().collect::<Vec<_>>()[..], [Component::Normal(name)] if name == user) {
+				return Some(Err(format!("{:?} is not a valid username", user)));
+			}
+			// a url of the user's root without the trailing slash is the same page
+			let path = rest.strip_prefix(suffix).or_else(|| rest.is_empty().then_some(""))?;
+			let path = percent_decode(&path[..path.find(['?', '#']).unwrap_or(path.len())]);
+			let path = Path::new(&path);
+			if path.components().any(|component| !matches!(component, Component::Normal(_))) {
+				return Some(Err("the path leaves the public directory".to_string()));
+			}
+			Some(Ok(self.homedirs.join(user).join(&self.public_dir_in_home).join(path)))
+		})
+	}
+
+	/// Look for a local file the same way the update reads parcel files, so links can't reveal anything update wouldn't
+	/// Only the existence of the file matters, so nothing is read from it
+	fn check_file(&self, path: PathBuf) -> LinkStatus {
+		let display = path.clone();
+		let Some(homedir) = path.strip_prefix(&self.homedirs).ok()
+			.and_then(|rest| rest.components().next())
+			.map(|user| self.homedirs.join(user)) else {
+			return LinkStatus::Broken(format!("{:?} is not in a home dir", display));
+		};
+		let drop_privileges = self.drop_privileges;
+		let result = self.limits.run(move ||
+			if drop_privileges {
+				privsep::read_home_file_as_owner(&homedir, &path, 0)
+			} else {
+				safefile::read_home_file(&homedir, &path, 0)
+			}
+		);
+		match result {
+			// a directory or a non-empty file, which are both fine to link to
+			Ok(Ok(_) | Err(FileError::TooLarge(_) | FileError::NotRegularFile)) => LinkStatus::Ok,
+			Ok(Err(FileError::NotFound)) => LinkStatus::Broken(format!("there is no file {:?}", display)),
+			// the web server may still serve these, so they are not reported as broken
+			Ok(Err(FileError::WrongOwner{..} | FileError::OutsideHome(_))) => LinkStatus::Unchecked,
+			Ok(Err(err)) => LinkStatus::Broken(format!("looking for {:?}: {}", display, err)),
+			Err(skipped) => LinkStatus::Broken(format!("looking for {:?}: {}", display, skipped))
+		}
+	}
+}
+
+/// Resolves host names like the default resolver, but leaves out any address that is not public
+#[derive(Debug, Default)]
+struct PublicResolver {
+	inner: DefaultResolver
+}
+
+impl Resolver for PublicResolver {
+	fn resolve(&self, uri: &ureq::http::Uri, config: &ureq::config::Config, timeout: NextTimeout) -> Result<ResolvedSocketAddrs, ureq::Error> {
+		let mut addrs = self.empty();
+		for addr in self.inner.resolve(uri, config, timeout)?.iter() {
+			if is_public_address(addr.ip()) {
+				addrs.push(*addr);
+			}
+		}
+		if addrs.is_empty() {
+			return Err(ureq::Error::Io(io::Error::other("the host does not have a public address")));
+		}
+		Ok(addrs)
+	}
+}
+
+/// Whether an address can be reached from anywhere on the internet, as opposed to loopback, private, link-local and other special addresses
+fn is_public_address(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => is_public_ipv4(ip),
+		IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+			Some(ip) => is_public_ipv4(ip),
+			None => is_public_ipv6(ip)
+		}
+	}
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+	let [a, b, ..] = ip.octets();
+	!(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast()
+		|| ip.is_documentation() || ip.is_multicast()
+		// 0.0.0.0/8 (this network), 100.64.0.0/10 (shared address space), 192.0.0.0/24 (protocol assignments), 198.18.0.0/15 (benchmarking) and 240.0.0.0/4 (reserved)
+		|| a == 0 || (a == 100 && b & 0xc0 == 64) || (a == 192 && b == 0 && ip.octets()[2] == 0) || (a == 198 && b & 0xfe == 18) || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+	let [first, second, ..] = ip.segments();
+	!(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast()
+		// fc00::/7 (unique local), fe80::/10 (link-local) and 2001:db8::/32 (documentation)
+		|| first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80 || (first == 0x2001 && second == 0xdb8))
+}
+
+fn check_http(agent: &ureq::Agent, url: &str) -> LinkStatus {
+	let mut response = agent.head(url).call();
+	// some servers don't support HEAD requests, but the page can still exist
+	if let Ok(head) = &response {
+		if head.status() == 405 || head.status() == 501 {
+			response = agent.get(url).call();
+		}
+	}
+	match response {
+		Ok(response) if response.status().is_client_error() || response.status().is_server_error() =>
+			LinkStatus::Broken(format!("the server responded with {}", response.status())),
+		Ok(_) => LinkStatus::Ok,
+		Err(err) => LinkStatus::Broken(err.to_string())
+	}
+}
+
+/// Replace the %XX escapes in a url path by the bytes they stand for
+fn percent_decode(text: &str) -> String {
+	let bytes = text.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		let escaped = (bytes[i] == b'%')
+			.then(|| text.get(i+1..i+3))
+			.flatten()
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+		match escaped {
+			Some(byte) => {
+				decoded.push(byte);
+				i += 3;
+			}
+			None => {
+				decoded.push(bytes[i]);
+				i += 1;
+			}
+		}
+	}
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The outcome of checking all links in the town
+/// The counts are per link, so a url that many parcels link to is counted for each of them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkCheckSummary {
+	pub ok: usize,
+	pub broken: usize,
+	pub unchecked: usize,
+	/// The distinct urls of the broken links
+	pub broken_urls: HashSet<String>
+}
+
+impl fmt::Display for LinkCheckSummary {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} link(s) work, {} are broken and {} were not checked", self.ok, self.broken, self.unchecked)
+	}
+}
+
+/// Check all links of all parcels in the town, adding the broken ones to the report, grouped by owner
+/// Each url is only checked once, even when many parcels link to it
+pub fn check_town(cadastre: &Cadastre, checker: &LinkChecker, report: &mut Report) -> LinkCheckSummary {
+	let mut parcels = cadastre.parcels();
+	parcels.sort_by_key(|parcel| (parcel.owner.users().join(" "), -parcel.owner.priority()));
+	let mut statuses: HashMap<&str, LinkStatus> = HashMap::new();
+	let mut summary = LinkCheckSummary::default();
+	for parcel in parcels {
+		for (_key, link) in parcel.links_in_order() {
+			let status = statuses.entry(&link.url).or_insert_with(|| checker.check(&link.url));
+			match status {
+				LinkStatus::Ok => summary.ok += 1,
+				LinkStatus::Unchecked => summary.unchecked += 1,
+				LinkStatus::Broken(reason) => {
+					summary.broken += 1;
+					summary.broken_urls.insert(link.url.clone());
+					report.add(parcel.owner.clone(), Some(parcel.location), ReportKind::BrokenLink(link.url.clone(), reason.clone()));
+				}
+			}
+		}
+	}
+	summary
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use crate::{Owner, Parcel, safefile::tests::test_dir};
+
+	fn checker(homedirs: &Path) -> LinkChecker {
+		LinkChecker {
+			local_urls: vec!["https://tilde.town/~{user}/".to_string()],
+			homedirs: homedirs.to_path_buf(),
+			public_dir_in_home: PathBuf::from("public_html"),
+			limits: Limits { read_timeout: Duration::from_secs(5), deadline: None },
+			drop_privileges: false,
+			http: None
+		}
+	}
+
+	#[test]
+	fn map_local_urls() {
+		let checker = checker(Path::new("/home"));
+		let local = |url| checker.local_path(url);
+		assert_eq!(local("https://tilde.town/~troido/cadastre/index.html?x=1#top"), Some(Ok(PathBuf::from("/home/troido/public_html/cadastre/index.html"))));
+		assert_eq!(local("https://tilde.town/~troido"), Some(Ok(PathBuf::from("/home/troido/public_html"))));
+		assert_eq!(local("https://tilde.town/~troido/my%20page.html"), Some(Ok(PathBuf::from("/home/troido/public_html/my page.html"))));
+		assert!(matches!(local("https://tilde.town/~troido/../../etc/passwd"), Some(Err(_))));
+		assert!(matches!(local("https://tilde.town/~troido/%2e%2e/x"), Some(Err(_))));
+		assert!(matches!(local("https://tilde.town/~../x"), Some(Err(_))));
+		assert!(matches!(local("https://tilde.town/~./x"), Some(Err(_))));
+		assert_eq!(local("https://tilde.town/~/x"), None);
+		assert_eq!(local("https://example.com/~troido/"), None);
+	}
+
+	#[test]
+	fn check_links_in_town() {
+		let homedirs = test_dir("linkcheck");
+		fs::create_dir_all(homedirs.join("troido/public_html")).unwrap();
+		fs::write(homedirs.join("troido/public_html/index.html"), "hi").unwrap();
+		std::os::unix::fs::symlink("/etc/passwd", homedirs.join("troido/public_html/passwd")).unwrap();
+		let text = "0 0\n1234\n\n\n\n\n\n\n\n\n\n\n\n-\n1 https://tilde.town/~troido/index.html\n2 https://tilde.town/~troido/gone.html\n3 gemini://example.com\n4 https://tilde.town/~troido/passwd";
+		let parcel = Parcel::from_text(text, Owner::user("troido")).unwrap();
+		let town = Cadastre::empty().with_parcel(parcel);
+		let mut report = Report::new();
+		let summary = check_town(&town, &checker(&homedirs), &mut report);
+		fs::remove_dir_all(&homedirs).unwrap();
+		assert_eq!(summary.ok, 1);
+		// the symlink out of the home dir is refused but not broken
+		assert_eq!(summary.unchecked, 2);
+		assert_eq!(summary.broken, 1);
+		assert_eq!(summary.broken_urls, HashSet::from(["https://tilde.town/~troido/gone.html".to_string()]));
+		assert_eq!(report.entries.len(), 1);
+		assert!(matches!(&report.entries[0].kind, ReportKind::BrokenLink(url, _) if url == "https://tilde.town/~troido/gone.html"));
+	}
+
+	#[test]
+	fn refuse_private_addresses() {
+		for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fe80::1", "fd00::1", "::ffff:127.0.0.1"] {
+			assert!(!is_public_address(ip.parse().unwrap()), "{} is not public", ip);
+		}
+		for ip in ["1.1.1.1", "93.184.216.34", "2606:4700::1111"] {
+			assert!(is_public_address(ip.parse().unwrap()), "{} is public", ip);
+		}
+		let checker = LinkChecker { http: Some(LinkChecker::http_agent(Duration::from_secs(5))), ..checker(Path::new("/home")) };
+		assert!(matches!(checker.check("http://127.0.0.1:1/"), LinkStatus::Broken(reason) if reason.contains("public address")));
+		assert!(matches!(checker.check("http://localhost:1/"), LinkStatus::Broken(reason) if reason.contains("public address")));
+	}
+}
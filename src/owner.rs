@@ -2,10 +2,13 @@
 use std::path::Path;
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
 
+/// Who a parcel belongs to, which decides the priority when multiple parcels claim the same plot
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Owner {
 	Admin,
 	User(String),
+	/// A plot that is shared by multiple users. The first user is the one whose parcel file is used
+	Shared(Vec<String>),
 	Public
 }
 
@@ -14,15 +17,41 @@ impl Owner {
 		match self {
 			Self::Admin => 3,
 			Self::User(_) => 2,
+			Self::Shared(_) => 2,
 			Self::Public => 1
 		}
 	}
+	/// The names of all users that own this
+	pub fn users(&self) -> Vec<&str> {
+		match self {
+			Self::User(name) => vec![name.as_str()],
+			Self::Shared(names) => names.iter().map(String::as_str).collect(),
+			Self::Admin | Self::Public => Vec::new()
+		}
+	}
+	/// The user whose plot quota is used for this
+	/// Admins and the public have no quota
+	pub fn quota_holder(&self) -> Option<&str> {
+		self.users().first().copied()
+	}
+	/// Whether the tenancy of one owner carries over to the other
+	/// This is the case when the owners are the same, or when they have a user in common
+	pub fn shares_tenancy_with(&self, other: &Owner) -> bool {
+		self == other || self.users().iter().any(|name| other.users().contains(name))
+	}
 	pub fn user(name: &str) -> Self {
 		Self::User(name.to_string())
 	}
 	pub fn from_homedir(homedir: &Path) -> Option<Self> {
 		Some(Self::user(homedir.file_name()?.to_str()?))
 	}
+	/// Whether a name that users wrote themselves can be a username
+	/// Usernames end up in paths and html ids, so only plain names are allowed
+	pub fn is_valid_username(name: &str) -> bool {
+		!name.is_empty()
+			&& !name.starts_with(['.', '-'])
+			&& name.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '.'))
+	}
 }
 
 
@@ -32,6 +61,7 @@ impl Serialize for Owner {
 		match self {
 			Self::Admin => "@_admin".serialize(serializer),
 			Self::User(name) => name.serialize(serializer),
+			Self::Shared(names) => names.serialize(serializer),
 			Self::Public => ().serialize(serializer)
 		}
 	}
@@ -39,23 +69,42 @@ impl Serialize for Owner {
 impl<'de> Deserialize<'de> for Owner {
 	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
 	where D: Deserializer<'de> {
-		Ok(match <Option<&str>>::deserialize(deserializer)? {
-			None => Self::Public,
-			Some("@_admin") => Self::Admin,
-			Some(name) => Self::user(name)
+		Ok(match OwnerRepr::deserialize(deserializer)? {
+			OwnerRepr::Single(None) => Self::Public,
+			OwnerRepr::Single(Some(name)) if name == "@_admin" => Self::Admin,
+			OwnerRepr::Single(Some(name)) => Self::User(name),
+			OwnerRepr::Multiple(names) => Self::Shared(names)
 		})
 	}
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OwnerRepr {
+	Single(Option<String>),
+	Multiple(Vec<String>)
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	#[test]
+	fn validate_usernames() {
+		assert!(Owner::is_valid_username("troido"));
+		assert!(Owner::is_valid_username("j.doe_2"));
+		assert!(!Owner::is_valid_username(""));
+		assert!(!Owner::is_valid_username(".."));
+		assert!(!Owner::is_valid_username("a/b"));
+		assert!(!Owner::is_valid_username("x\"><script>"));
+	}
+
 	#[test]
 	fn serialize_owner(){
 		assert_eq!(serde_json::json!(Owner::Admin).to_string(), "\"@_admin\"");
 		assert_eq!(serde_json::json!(Owner::user("troido")).to_string(), "\"troido\"");
 		assert_eq!(serde_json::json!(Owner::Public).to_string(), "null");
+		assert_eq!(serde_json::json!(Owner::Shared(vec!["troido".to_string(), "odiort".to_string()])).to_string(), "[\"troido\",\"odiort\"]");
 	}
 
 	#[test]
@@ -66,5 +115,18 @@ mod tests {
 		assert_eq!(serde_json::from_str::<Owner>("\"@_admin\"").unwrap(), Owner::Admin);
 		assert_eq!(serde_json::from_str::<Owner>("\"troido\"").unwrap(), Owner::user("troido"));
 		assert_eq!(serde_json::from_str::<Owner>("null").unwrap(), Owner::Public);
+		assert_eq!(serde_json::from_str::<Owner>("[\"troido\", \"odiort\"]").unwrap(), Owner::Shared(vec!["troido".to_string(), "odiort".to_string()]));
+		assert!(serde_json::from_str::<Owner>("[3]").is_err());
+	}
+
+	#[test]
+	fn shared_owners_share_tenancy() {
+		let shared = Owner::Shared(vec!["troido".to_string(), "odiort".to_string()]);
+		assert!(shared.shares_tenancy_with(&Owner::user("odiort")));
+		assert!(Owner::user("troido").shares_tenancy_with(&shared));
+		assert!(!shared.shares_tenancy_with(&Owner::user("john")));
+		assert!(!Owner::Admin.shares_tenancy_with(&Owner::Public));
+		assert_eq!(shared.quota_holder(), Some("troido"));
+		assert_eq!(Owner::Public.quota_holder(), None);
 	}
 }
@@ -2,19 +2,21 @@
 use std::fmt;
 use std::collections::{HashMap, HashSet};
 use serde::{Serialize, Deserialize};
-use lazy_static::lazy_static;
+use unicode_width::UnicodeWidthChar;
 use crate::{
   pos::Pos,
   strutil,
-  owner::Owner
+  owner::Owner,
+  charset::{Charset, DEFAULT_CHARSET},
+  escape,
+  link::Link
 };
 
 pub const PLOT_WIDTH: usize = 24;
 pub const PLOT_HEIGHT: usize = 12;
-lazy_static! {
-	static ref allowed_characters: HashSet<char> = " !\"#$%&\'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~¥¨°²´·¿×ōπᓚᗢᘏ†•…‾∞≈≡⊞─│┌┏┐┓└┗┘┛├┣┤┫┬┳┴┻┼╂═║╒╔╕╗╘╚╛╜╝╟╠╢╣╤╥╦╧╩╫╭╮╰╱╲╿▀▁▂▃▄█▉▊▌▎▐░▒▓▔▙▛▜▟▪►◄◊◘◠☆☺♠♥♪♫♯⚵⚶⛭✥✽❀➅➐⠀⠁⠃⠈⠋⠘⠙⠛⠞⠟⠳⠺⠾⡀⡇⡞⡤⢀⢇⢠⢤⢦⢩⢫⢸⢹⢻⢾⢿⣀⣄⣆⣠⣤⣬⣯⣳⣴⣷⣻⣼⣽⣿".chars().collect();
-}
 
+/// A single plot of art of PLOT_WIDTH by PLOT_HEIGHT characters
+/// The mask has the same size as the art, and each character in the mask that is a key in the links will make that position link there
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Parcel {
 	pub owner: Owner,
@@ -22,31 +24,115 @@ pub struct Parcel {
 	pub art: Vec<String>,
 	#[serde(default, rename="linkmask")]
 	pub mask: Vec<String>,
-	pub links: HashMap<char, String>
+	pub links: HashMap<char, Link>,
+	/// A short name for the plot, from the '#title' header
+	#[serde(default, skip_serializing_if="Option::is_none")]
+	pub title: Option<String>,
+	/// A longer text about the plot, from the '#desc' headers
+	#[serde(default, skip_serializing_if="Option::is_none")]
+	pub description: Option<String>,
+	/// Words to find the plot by, from the '#tags' headers
+	#[serde(default, skip_serializing_if="Vec::is_empty")]
+	pub tags: Vec<String>,
+	/// What the art shows, for people who can't see it, from the '#alt' header
+	#[serde(default, skip_serializing_if="Option::is_none")]
+	pub alt: Option<String>,
+	/// Additional parcels of a user are named after their file so the same plot can be recognised in later updates
+	#[serde(default, skip_serializing_if="Option::is_none")]
+	pub name: Option<String>,
+	/// Parcels of users who have been inactive for a long time can be claimed by active users
+	#[serde(default, skip_serializing_if="std::ops::Not::not")]
+	pub inactive: bool,
+	/// The number of updates in a row that this parcel has been kept while the parcel file couldn't be parsed
+	#[serde(default, skip_serializing_if="is_zero")]
+	pub stale: u32
+}
+
+fn is_zero(n: &u32) -> bool {
+	*n == 0
 }
 
 impl Parcel {
-	#[allow(dead_code)]
+	/// A parcel with only whitespace
 	pub fn empty(owner: Owner, location: Pos) -> Self {
 		Self {
 			owner,
 			location,
-			art: std::iter::repeat(" ".repeat(24)).take(12).collect(),
-			mask: std::iter::repeat(" ".repeat(24)).take(12).collect(),
-			links: HashMap::new()
+			art: std::iter::repeat_n(" ".repeat(24), 12).collect(),
+			mask: std::iter::repeat_n(" ".repeat(24), 12).collect(),
+			links: HashMap::new(),
+			title: None,
+			description: None,
+			tags: Vec::new(),
+			alt: None,
+			name: None,
+			inactive: false,
+			stale: 0
 		}
 	}
 
+	/// Parse a parcel from the parcel file format, with the default set of allowed characters
+	/// The owner is not part of the file; it is decided by where the file was found
 	pub fn from_text(text: &str, owner: Owner) -> Result<Self, ParseError> {
-		let mut lines = text.lines().enumerate();
-		// first line is the location of the plot: 2 integers separated by whitespace
-		let (_, first_line) = lines.next().ok_or(ParseError{ kind: ParseErrorKind::EmptyFile, row: 0, line: "".to_string() })?;
+		Self::from_text_with_charset(text, owner, &DEFAULT_CHARSET).map(|(parcel, _)| parcel)
+	}
+
+	/// Parse a parcel from the parcel file format, replacing all characters in the art and mask that are not in the charset
+	/// Besides the parcel, this returns the characters that were replaced, so users can be told what went wrong
+	pub fn from_text_with_charset(text: &str, owner: Owner, charset: &Charset) -> Result<(Self, Vec<Replacement>), ParseError> {
+		let mut replacements = Vec::new();
+		let mut lines = text.lines().enumerate().peekable();
+		// the file can start with header lines: a '#' directly followed by a key, and then the value for that key
+		// '#with' followed by usernames separated by whitespace will share the plot with those users
+		// '#escapes' makes escapes like '\u{2554}' and ':box_tl:' in the art and mask stand for the characters that are hard to type
+		// '#title', '#desc' and '#tags' describe the plot; each '#desc' line adds a line to the description, and tags are separated by commas
+		// '#alt' describes the art for screen readers
+		let mut co_owners: Vec<String> = Vec::new();
+		let mut escapes = false;
+		let mut title: Option<String> = None;
+		let mut description: Option<String> = None;
+		let mut tags: Vec<String> = Vec::new();
+		let mut alt: Option<String> = None;
+		while let Some((row, line)) = lines.next_if(|(_, line)| line.starts_with('#')) {
+			let (key, value) = split_header(line);
+			match key {
+				"with" => {
+					if !value.split_whitespace().all(Owner::is_valid_username) {
+						return Err(ParseError{ kind: ParseErrorKind::CoOwner, row, line: line.to_string() });
+					}
+					co_owners.extend(value.split_whitespace().map(String::from))
+				}
+				"escapes" => escapes = true,
+				"title" => title = Some(value.to_string()),
+				"desc" => description = Some(match description {
+					Some(description) => format!("{}\n{}", description, value),
+					None => value.to_string()
+				}),
+				"tags" => tags.extend(value.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(String::from)),
+				"alt" => alt = Some(value.to_string()),
+				_ => return Err(ParseError{ kind: ParseErrorKind::HeaderLine, row, line: line.to_string() })
+			}
+		}
+		let owner = match owner {
+			Owner::User(name) if !co_owners.is_empty() => {
+				let mut names = vec![name];
+				for co_owner in co_owners {
+					if !names.contains(&co_owner) {
+						names.push(co_owner);
+					}
+				}
+				Owner::Shared(names)
+			}
+			owner => owner
+		};
+		// first line after the headers is the location of the plot: 2 integers separated by whitespace
+		let (row, first_line) = lines.next().ok_or(ParseError{ kind: ParseErrorKind::EmptyFile, row: 0, line: "".to_string() })?;
 		let location: Pos = Pos::from_space_separated(first_line)
-			.ok_or(ParseError{ kind: ParseErrorKind::PosLine, row: 0, line: first_line.to_string() })?;
+			.ok_or(ParseError{ kind: ParseErrorKind::PosLine, row, line: first_line.to_string() })?;
 		// the next 12 lines are the art that is actually drawn
 		// if there are less than 12 lines or less than 24 characters per line then the missing area is filled in with whitespace
 		// any characters after 24 are ignored
-		let art: Vec<String> = read_plot(&mut lines);
+		let art: Vec<String> = read_plot(&mut lines, escapes, charset, &mut replacements);
 		// If the separator line is an empty line, then the 12 lines after that are the mask
 		// If the separator line is a single dash then the mask is the same as the art
 		// If the end of the file has been reached then it doesn't matter what the mask is since it is not used
@@ -55,9 +141,9 @@ impl Parcel {
 			if let Some((_row, line)) = lines.next() {
 				match line.trim() {
 					"-" => art.clone(),
-					"" => read_plot(&mut lines),
+					"" => read_plot(&mut lines, escapes, charset, &mut replacements),
 					_ => {
-						lines = "".lines().enumerate(); // don't read any more lines
+						lines = "".lines().enumerate().peekable(); // don't read any more lines
 						art.clone()
 					}
 				}
@@ -66,7 +152,8 @@ impl Parcel {
 			};
 		// all remaining lines are link definitions
 		// they consist of the key (a single non-whitespace character that should occur in the mask), and a link (separated by whitespace)
-		let mut links: HashMap<char, String> = HashMap::new();
+		// the link can be followed by a quoted title and attributes, like: 1 https://tilde.town/~troido/ "My homepage" rel=me
+		let mut links: HashMap<char, Link> = HashMap::new();
 		for (row, line_raw) in lines {
 			let line = line_raw.trim();
 			if line.is_empty() { continue; }
@@ -74,38 +161,208 @@ impl Parcel {
 				.ok_or(ParseError{ kind: ParseErrorKind::LinkLine, row, line: line.to_string() })?;
 			let key: char = strutil::to_char(charpart)
 				.ok_or(ParseError{ kind: ParseErrorKind::LinkLine, row, line: line.to_string() })?;
-			links.insert(key, link.to_string());
+			links.insert(key, Link::parse(link));
+		}
+		Ok((Self {owner, location, art, mask, links, title, description, tags, alt, name: None, inactive: false, stale: 0}, replacements))
+	}
+
+	/// Write the parcel in the parcel file format, so that parsing it again gives the same parcel
+	/// The owner is only included when it is shared; the other users are then listed in a '#with' header
+	/// Trailing whitespace is left out and the links are sorted by key
+	pub fn to_text(&self) -> String {
+		let mut text = String::new();
+		if let Owner::Shared(users) = &self.owner {
+			text.push_str(&format!("#with {}\n", users[1..].join(" ")));
+		}
+		if let Some(title) = &self.title {
+			text.push_str(&format!("#title {}\n", title));
+		}
+		if let Some(description) = &self.description {
+			for line in description.split('\n') {
+				text.push_str(&format!("#desc {}\n", line));
+			}
 		}
-		Ok(Self {owner, location, art, mask, links})
+		if !self.tags.is_empty() {
+			text.push_str(&format!("#tags {}\n", self.tags.join(", ")));
+		}
+		if let Some(alt) = &self.alt {
+			text.push_str(&format!("#alt {}\n", alt));
+		}
+		text.push_str(&format!("{} {}\n", self.location.x, self.location.y));
+		for line in &self.art {
+			text.push_str(&format!("{}\n", line.trim_end()));
+		}
+		if self.mask == self.art {
+			text.push_str("-\n");
+		} else {
+			text.push('\n');
+			for line in &self.mask {
+				text.push_str(&format!("{}\n", line.trim_end()));
+			}
+		}
+		let mut links: Vec<(&char, &Link)> = self.links.iter().collect();
+		links.sort_by_key(|(key, _)| **key);
+		for (key, link) in links {
+			text.push_str(&format!("{} {}\n", key, link.to_text()));
+		}
+		text
+	}
+
+	/// The priority when claiming a plot: first the priority of the owner, then whether it is still active
+	pub fn priority(&self) -> (i32, bool) {
+		(self.owner.priority(), !self.inactive)
+	}
+
+	/// Whether both parcels are the same plot of the same owner, used to decide tenancy
+	pub fn is_same_plot(&self, other: &Parcel) -> bool {
+		self.owner.shares_tenancy_with(&other.owner) && self.name == other.name
 	}
 
+	/// The text shown when hovering over the plot: the title, description and tags, if there are any
+	pub fn tooltip(&self) -> Option<String> {
+		let mut parts: Vec<String> = self.title.iter().chain(&self.description).cloned().collect();
+		if !self.tags.is_empty() {
+			parts.push(self.tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<String>>().join(" "));
+		}
+		if parts.is_empty() {
+			None
+		} else {
+			Some(parts.join("\n"))
+		}
+	}
+
+	/// Whether the title, description or one of the tags contains the text, ignoring case
+	pub fn matches_text(&self, text: &str) -> bool {
+		let text = text.to_lowercase();
+		self.title.iter()
+			.chain(&self.description)
+			.chain(&self.tags)
+			.any(|field| field.to_lowercase().contains(&text))
+	}
+
+	/// Whether the parcel has the tag, ignoring case
+	pub fn has_tag(&self, tag: &str) -> bool {
+		self.tags.iter().any(|own| own.to_lowercase() == tag.to_lowercase())
+	}
+
+	/// The alt text of the plot, or a description of where it is and whose it is if the owner did not write one
+	pub fn alt_text(&self) -> String {
+		if let Some(alt) = &self.alt {
+			return alt.clone();
+		}
+		let mut owners: Vec<String> = self.owner.users().iter().map(|name| format!("~{}", name)).collect();
+		let Some(last) = owners.pop() else {
+			return format!("Plot at {},{}", self.location.x, self.location.y);
+		};
+		if owners.is_empty() {
+			format!("Plot of {} at {},{}", last, self.location.x, self.location.y)
+		} else {
+			format!("Plot of {} and {} at {},{}", owners.join(", "), last, self.location.x, self.location.y)
+		}
+	}
+
+	/// The links in the order they first appear in the mask, reading from left to right and top to bottom
+	/// Links that don't appear in the mask are left out, since they can't be clicked
+	pub fn links_in_order(&self) -> Vec<(char, &Link)> {
+		let mut keys: Vec<char> = Vec::new();
+		for ch in self.mask.iter().flat_map(|line| line.chars()) {
+			if self.links.contains_key(&ch) && !keys.contains(&ch) {
+				keys.push(ch);
+			}
+		}
+		keys.into_iter().map(|key| (key, &self.links[&key])).collect()
+	}
+
+	/// A single line of the art as plain text
 	pub fn text_line(&self, y: usize) -> &str {
 		&self.art[y]
 	}
 
+	/// A single line of the art as html, with the links and, on the first line, the anchors for the owners
 	pub fn html_line(&self, y: usize) -> String {
+		self.html_line_with(y, &HtmlLineOptions { broken_links: &HashSet::new(), user_anchor: true, accessible: false })
+	}
+
+	/// A single line of the art as html, with settings that come from the page it is rendered in
+	pub fn html_line_with(&self, y: usize, options: &HtmlLineOptions) -> String {
 		let mut line = String::with_capacity(PLOT_WIDTH);
-		let mut opened = false;
-		if y == 0 {
-			if let Owner::User(name) = &self.owner {
-				// The first line of a plot should have the username as anchor
-				opened = true;
-				line.push_str(&format!("<span id=\"{}\">", name));
+		let mut opened = 0;
+		if self.inactive {
+			opened += 1;
+			line.push_str("<span class=\"inactive\">");
+		}
+		if let Some(tooltip) = self.tooltip() {
+			opened += 1;
+			line.push_str(&format!("<span title=\"{}\"", escape_html(&tooltip)));
+			if y == 0 {
+				// The first line also has the metadata separately, for scripts and other tools reading the page
+				if let Some(title) = &self.title {
+					line.push_str(&format!(" data-title=\"{}\"", escape_html(title)));
+				}
+				if let Some(description) = &self.description {
+					line.push_str(&format!(" data-desc=\"{}\"", escape_html(description)));
+				}
+				if !self.tags.is_empty() {
+					line.push_str(&format!(" data-tags=\"{}\"", escape_html(&self.tags.join(","))));
+				}
 			}
+			line.push('>');
 		}
-		let mut active_key: Option<char> = None;
-		for (ch, mch) in self.art[y].chars().zip(self.mask[y].chars()) {
-			// if the last char had a link and this one does not or has a different link, then close it
-			if active_key.is_some_and(|k| k != mch) {
-				line.push_str("</a>");
-				active_key = None;
+		if y == 0 && options.user_anchor {
+			// Co-owners have their own plots for their anchor
+			if let Some(name) = self.owner.quota_holder() {
+				opened += 1;
+				line.push_str(&format!("<span id=\"{}\">", escape_html(name)));
 			}
-			// if no link is active and this char has a link, then open the link
-			if let Some(link) = self.links.get(&mch) {
-				if active_key.is_none() {
-					line.push_str(&format!("<a href=\"{}\">", link.replace('"', "&quot;")));
-					active_key = Some(mch);
+		}
+		// For screen readers the plot is an image, labelled with the alt text on its first line
+		// The rest of the art is decoration that is hidden from them, but the links stay reachable, named by their title or url
+		// If the first line has no decoration to label, an empty labelled span stands in for the image
+		let accessible = options.accessible;
+		let mut labelled = y != 0 || !accessible;
+		// the mask is matched to the art by cell, so wide characters in either don't shift the links
+		let mask_keys: Vec<char> = self.mask[y].chars()
+			.flat_map(|ch| std::iter::repeat_n(ch, char_width(ch)))
+			.collect();
+		let mut cell = 0;
+		let mut run: Option<Run> = None;
+		for ch in self.art[y].chars() {
+			let mch = mask_keys.get(cell).copied().unwrap_or(' ');
+			cell += char_width(ch);
+			let next = if self.links.contains_key(&mch) { Run::Link(mch) } else { Run::Decoration };
+			if run != Some(next) {
+				// close the previous run before starting a new one
+				match run {
+					Some(Run::Link(_)) => line.push_str("</a>"),
+					Some(Run::Decoration) if accessible => line.push_str("</span>"),
+					_ => {}
 				}
+				match next {
+					Run::Link(key) => {
+						let link = &self.links[&key];
+						line.push_str(&format!("<a href=\"{}\"", link.url.replace('"', "&quot;")));
+						if let Some(title) = &link.title {
+							line.push_str(&format!(" title=\"{}\"", escape_html(title)));
+						}
+						if options.broken_links.contains(&link.url) {
+							line.push_str(" class=\"broken\"");
+						}
+						for (name, value) in &link.attributes {
+							line.push_str(&format!(" {}=\"{}\"", escape_html(name), escape_html(value)));
+						}
+						if accessible {
+							line.push_str(&format!(" aria-label=\"{}\"", escape_html(link.name())));
+						}
+						line.push('>');
+					}
+					Run::Decoration if !accessible => {}
+					Run::Decoration if !labelled => {
+						line.push_str(&format!("<span role=\"img\" aria-label=\"{}\">", escape_html(&self.alt_text())));
+						labelled = true;
+					}
+					Run::Decoration => line.push_str("<span aria-hidden=\"true\">")
+				}
+				run = Some(next);
 			}
 			// replace html unsafe characters
 			if ch == '<' {
@@ -118,32 +375,175 @@ impl Parcel {
 				line.push(ch);
 			}
 		}
-		if active_key.is_some() {
-			line.push_str("</a>");
+		match run {
+			Some(Run::Link(_)) => line.push_str("</a>"),
+			Some(Run::Decoration) if accessible => line.push_str("</span>"),
+			_ => {}
+		}
+		if !labelled {
+			line.push_str(&format!("<span role=\"img\" aria-label=\"{}\"></span>", escape_html(&self.alt_text())));
 		}
-		if opened {
+		for _ in 0..opened {
 			line.push_str("</span>");
 		}
 		line
 	}
 }
 
+/// Settings for rendering a line of art as html that come from the page it is rendered in
+#[derive(Debug, Clone, Copy)]
+pub struct HtmlLineOptions<'a> {
+	/// Links to these urls get the 'broken' class
+	pub broken_links: &'a HashSet<String>,
+	/// Whether the first line has the user whose parcel file it is as anchor
+	pub user_anchor: bool,
+	/// Whether the plot is labelled with its alt text for screen readers, with the links named and the rest of the art hidden from them
+	pub accessible: bool
+}
+
+/// A stretch of a line of art that is rendered as one element: a link, or decoration without a link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Run {
+	Link(char),
+	Decoration
+}
+
+/// Make text safe to use in html, including attribute values
+pub(crate) fn escape_html(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+/// The text of a new parcel file: an empty bordered plot, signed with the name of the user, where the signature links to the given url
+pub fn template_text(location: Pos, username: &str, link: &str) -> String {
+	let inner = PLOT_WIDTH - 2;
+	let signature: String = format!("~{}", username).chars().take(inner - 1).collect();
+	let signature_mask = "1".repeat(signature.chars().count());
+	let bordered = |signed: &str| -> Vec<String> {
+		let border = format!("+{}+", "-".repeat(inner));
+		let mut lines = vec![border.clone()];
+		lines.extend(std::iter::repeat_n(format!("|{}|", " ".repeat(inner)), PLOT_HEIGHT - 3));
+		lines.push(format!("|{:>width$} |", signed, width = inner - 1));
+		lines.push(border);
+		lines
+	};
+	let parcel = Parcel {
+		art: bordered(&signature),
+		mask: bordered(&signature_mask),
+		links: HashMap::from([('1', Link::parse(link))]),
+		..Parcel::empty(Owner::user(username), location)
+	};
+	parcel.to_text()
+}
+
+fn split_header(line: &str) -> (&str, &str) {
+	let header = line.trim_start_matches('#').trim();
+	match header.split_once(char::is_whitespace) {
+		Some((key, value)) => (key, value.trim()),
+		None => (header, "")
+	}
+}
+
+/// The number of cells that a character takes on the screen: 2 for wide characters, 1 for everything else
+/// Zero-width characters never end up in a plot
+pub fn char_width(ch: char) -> usize {
+	ch.width().unwrap_or(1).max(1)
+}
 
-fn process_plot_line(txt: &str, length: usize) -> String {
-	String::from_iter(
-		txt.chars()
-			.chain(std::iter::repeat(' '))
-			.take(length)
-			.map(|ch| if allowed_characters.contains(&ch) { ch } else { '?' })
-	)
+/// The character that starts in each cell of a line of art
+/// The second cell of a wide character is None
+pub fn line_cells(line: &str) -> Vec<Option<char>> {
+	let mut cells = Vec::with_capacity(PLOT_WIDTH);
+	for ch in line.chars() {
+		cells.push(Some(ch));
+		if char_width(ch) == 2 {
+			cells.push(None);
+		}
+	}
+	cells
+}
+
+/// Make a line of art exactly the right width on screen, replace all characters that are not allowed with '?', and leave out zero-width characters
+/// The replaced characters are added to the replacements, with the given row as their line number
+pub(crate) fn process_plot_line(txt: &str, length: usize, charset: &Charset, row: usize, replacements: &mut Vec<Replacement>) -> String {
+	let mut line = String::with_capacity(length);
+	let mut width = 0;
+	for (column, ch) in txt.chars().enumerate() {
+		if width >= length {
+			break;
+		}
+		let ch = match ch.width() {
+			// combining characters would change the previous character, and other zero-width characters can't be seen
+			Some(0) => {
+				replacements.push(Replacement { row, column, ch, reason: ReplaceReason::ZeroWidth });
+				continue;
+			}
+			_ if !charset.contains(ch) => {
+				replacements.push(Replacement { row, column, ch, reason: ReplaceReason::NotAllowed });
+				'?'
+			}
+			_ => ch
+		};
+		// a wide character that doesn't fit in the last cell is cut off like everything after it
+		if width + char_width(ch) > length {
+			break;
+		}
+		line.push(ch);
+		width += char_width(ch);
+	}
+	line.extend(std::iter::repeat_n(' ', length - width));
+	line
 }
 
-fn read_plot<'a>(lines: &mut impl Iterator<Item=(usize, &'a str)>) -> Vec<String> {
+fn read_plot<'a>(lines: &mut impl Iterator<Item=(usize, &'a str)>, escapes: bool, charset: &Charset, replacements: &mut Vec<Replacement>) -> Vec<String> {
 	(0..PLOT_HEIGHT)
-		.map(|_| process_plot_line(lines.next().unwrap_or((0, "")).1, PLOT_WIDTH))
+		.map(|_| {
+			let (row, line) = lines.next().unwrap_or((0, ""));
+			// escapes are expanded first, so the characters they stand for are filtered like any other character
+			if escapes {
+				let (expanded, columns) = escape::expand_with_columns(line);
+				let start = replacements.len();
+				let processed = process_plot_line(&expanded, PLOT_WIDTH, charset, row, replacements);
+				// the replacements point into the expanded line, but the user wants to know where to look in their file
+				for replacement in &mut replacements[start..] {
+					replacement.column = columns[replacement.column];
+				}
+				processed
+			} else {
+				process_plot_line(line, PLOT_WIDTH, charset, row, replacements)
+			}
+		})
 		.collect::<Vec<String>>()
 }
 
+/// A character in the art or mask of a parcel file that was replaced with '?' or left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+	pub row: usize,
+	pub column: usize,
+	pub ch: char,
+	pub reason: ReplaceReason
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceReason {
+	NotAllowed,
+	ZeroWidth
+}
+
+impl fmt::Display for Replacement {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "line {}, column {}: {:?} (U+{:04X}) ", self.row + 1, self.column + 1, self.ch, self.ch as u32)?;
+		match self.reason {
+			ReplaceReason::NotAllowed => write!(f, "is not one of the allowed characters, so it is replaced with '?'"),
+			ReplaceReason::ZeroWidth => write!(f, "has no width of its own, so it is left out")
+		}
+	}
+}
+
+/// What went wrong while parsing a parcel file, and where
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParseError {
 	pub kind: ParseErrorKind,
@@ -153,17 +553,21 @@ pub struct ParseError {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParseErrorKind {
 	EmptyFile,
+	HeaderLine,
 	PosLine,
 	SeparatorLine,
-	LinkLine
+	LinkLine,
+	CoOwner
 }
 impl fmt::Display for ParseError {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		let message = match self.kind {
 			ParseErrorKind::EmptyFile => "The file is empty",
+			ParseErrorKind::HeaderLine => "Header lines at the start of the file must be a '#' followed by a known key (with, escapes, title, desc, tags, alt) and its value",
 			ParseErrorKind::PosLine => "The first line must contain to position of the plot as 2 integers separated by a space",
 			ParseErrorKind::SeparatorLine => "After the plot there must be a separator line that's either empty or only contains a '-' character",
-			ParseErrorKind::LinkLine => "Each line line must start with a key (single character), followed by a space, followed by the link"
+			ParseErrorKind::LinkLine => "Each line line must start with a key (single character), followed by a space, followed by the link",
+			ParseErrorKind::CoOwner => "The '#with' header must be followed by usernames separated by spaces"
 		};
 		write!(f, "Parse error: {}\n on line {}: \"{}\"", message, self.row + 1, self.line)
 	}
@@ -174,6 +578,92 @@ impl std::error::Error for ParseError {}
 mod tests {
 	use super::*;
 	use crate::hashmap;
+	use proptest::prelude::*;
+	use proptest::collection::{vec, hash_map, btree_map, btree_set};
+	use crate::link::LINK_ATTRIBUTES;
+	use proptest::sample::select;
+
+	fn plot_lines() -> impl Strategy<Value = Vec<String>> {
+		vec(vec(select(DEFAULT_CHARSET.chars().collect::<Vec<char>>()), PLOT_WIDTH).prop_map(String::from_iter), PLOT_HEIGHT)
+	}
+
+	fn owner() -> impl Strategy<Value = Owner> {
+		prop_oneof![
+			Just(Owner::Admin),
+			Just(Owner::Public),
+			"[a-z][a-z0-9_]{0,8}".prop_map(Owner::User),
+			btree_set("[a-z][a-z0-9_]{0,8}", 2..5).prop_map(|names| Owner::Shared(names.into_iter().collect()))
+		]
+	}
+
+	fn link() -> impl Strategy<Value = Link> {
+		// a url with whitespace can only be written without title and attributes, since those would be read as part of the url
+		prop_oneof![
+			"[!-~]([ -~]{0,30}[!-~])?".prop_filter("the url can't look like it has a title or attributes", |url| Link::parse(url) == Link::new(url)).prop_map(|url| Link::new(&url)),
+			(
+				"[!-~]{1,30}",
+				proptest::option::of("[ -~]{0,20}"),
+				btree_map(select(LINK_ATTRIBUTES), "[!-~]{1,10}", 0..3)
+			).prop_map(|(url, title, attributes)| Link {
+				url,
+				title,
+				attributes: attributes.into_iter().map(|(name, value)| (name.to_string(), value)).collect()
+			})
+		]
+	}
+
+	fn parcel() -> impl Strategy<Value = Parcel> {
+		let key = select(DEFAULT_CHARSET.chars().collect::<Vec<char>>()).prop_filter("link keys can't be whitespace", |ch| !ch.is_whitespace());
+		(
+			owner(),
+			any::<(i64, i64)>(),
+			plot_lines(),
+			prop_oneof![Just(None), plot_lines().prop_map(Some)],
+			hash_map(key, link(), 0..5),
+			proptest::option::of("[!-~]([ -~]{0,20}[!-~])?"),
+			proptest::option::of(vec("[!-~]([ -~]{0,20}[!-~])?", 1..4).prop_map(|lines| lines.join("\n"))),
+			vec("[a-z0-9]([a-z0-9 _-]{0,8}[a-z0-9])?", 0..4),
+			proptest::option::of("[!-~]([ -~]{0,20}[!-~])?")
+		).prop_map(|(owner, location, art, mask, links, title, description, tags, alt)| Parcel {
+			owner,
+			location: Pos::from_tuple(location),
+			mask: mask.unwrap_or_else(|| art.clone()),
+			art,
+			links,
+			title,
+			description,
+			tags,
+			alt,
+			name: None,
+			inactive: false,
+			stale: 0
+		})
+	}
+
+	fn plot_line(txt: &str, length: usize) -> String {
+		process_plot_line(txt, length, &DEFAULT_CHARSET, 0, &mut Vec::new())
+	}
+
+	/// The owner that the file of a parcel would have been found under
+	fn file_owner(owner: &Owner) -> Owner {
+		match owner {
+			Owner::Shared(users) => Owner::user(&users[0]),
+			owner => owner.clone()
+		}
+	}
+
+	proptest! {
+		#[test]
+		fn round_trip_text(parcel in parcel()) {
+			prop_assert_eq!(Parcel::from_text(&parcel.to_text(), file_owner(&parcel.owner)), Ok(parcel));
+		}
+	}
+
+	#[test]
+	fn write_text() {
+		let parcel = Parcel::from_text(&format!("#with odiort\n3 -4\nhello  \n\n world\n{}-\n2 https://example.com/2\n1 https://example.com/1", "\n".repeat(9)), Owner::user("troido")).unwrap();
+		assert_eq!(parcel.to_text(), format!("#with odiort\n3 -4\nhello\n\n world\n{}-\n1 https://example.com/1\n2 https://example.com/2\n", "\n".repeat(9)));
+	}
 
 	#[test]
 	fn parse_error_when_empty() {
@@ -189,28 +679,183 @@ mod tests {
 		assert_eq!(Parcel::from_text("10 11 12", Owner::Public).unwrap_err().kind, ParseErrorKind::PosLine);
 	}
 
+	#[test]
+	fn parse_error_when_header_unknown() {
+		assert_eq!(Parcel::from_text("#foo bar\n1 2", Owner::Public).unwrap_err().kind, ParseErrorKind::HeaderLine);
+		assert_eq!(Parcel::from_text("#with troido", Owner::Public).unwrap_err().kind, ParseErrorKind::EmptyFile);
+		assert_eq!(Parcel::from_text("#with troido\n# 1 2", Owner::Public).unwrap_err().row, 1);
+	}
+
+	#[test]
+	fn parse_shared_parcel() {
+		let parcel = Parcel::from_text("#with odiort  john\n#with troido odiort\n3 4\nhello", Owner::user("troido")).unwrap();
+		assert_eq!(parcel.owner, Owner::Shared(vec!["troido".to_string(), "odiort".to_string(), "john".to_string()]));
+		assert_eq!(parcel.location, Pos::new(3, 4));
+		assert_eq!(parcel.art[0], "hello                   ");
+		assert_eq!(parcel.html_line(0), "<span id=\"troido\">hello                   </span>");
+		assert_eq!(Parcel::from_text("#with x\"><script>\n3 4\nhello", Owner::user("troido")).unwrap_err().kind, ParseErrorKind::CoOwner);
+		assert_eq!(Parcel::from_text("#with ../bob\n3 4\nhello", Owner::user("troido")).unwrap_err().kind, ParseErrorKind::CoOwner);
+	}
+
+	#[test]
+	fn expand_escapes_when_enabled() {
+		let text = "#escapes\n1 2\n:box_tl:\\u{2550}:box_tr: \\\\o/\n\n11\n\n1 https://example.com";
+		let parcel = Parcel::from_text(text, Owner::Public).unwrap();
+		assert_eq!(parcel.art[0], format!("╔═╗ \\o/{}", " ".repeat(17)));
+		let parcel = Parcel::from_text(&text.replace("#escapes\n", ""), Owner::Public).unwrap();
+		assert_eq!(parcel.art[0], ":box_tl:\\u{2550}:box_tr:");
+		let (_, replacements) = Parcel::from_text_with_charset("#escapes\n1 2\n:box_tl:\\u{1F47B}x:heart:", Owner::Public, &DEFAULT_CHARSET).unwrap();
+		assert_eq!(replacements, vec![Replacement { row: 2, column: 8, ch: '👻', reason: ReplaceReason::NotAllowed }]);
+	}
+
+	#[test]
+	fn parse_metadata() {
+		let text = "#title My cottage\n#desc A small house\n#desc with \"cats\"\n#tags garden, cats,\n#tags  \n3 4\nhello";
+		let parcel = Parcel::from_text(text, Owner::user("troido")).unwrap();
+		assert_eq!(parcel.title, Some("My cottage".to_string()));
+		assert_eq!(parcel.description, Some("A small house\nwith \"cats\"".to_string()));
+		assert_eq!(parcel.tags, vec!["garden".to_string(), "cats".to_string()]);
+		assert!(parcel.matches_text("HOUSE"));
+		assert!(parcel.matches_text("gard"));
+		assert!(!parcel.matches_text("dog"));
+		assert!(parcel.has_tag("Cats"));
+		assert!(!parcel.has_tag("cat"));
+		let tooltip = "My cottage\nA small house\nwith &quot;cats&quot;\n#garden #cats";
+		assert_eq!(
+			parcel.html_line(0),
+			format!("<span title=\"{}\" data-title=\"My cottage\" data-desc=\"A small house\nwith &quot;cats&quot;\" data-tags=\"garden,cats\"><span id=\"troido\">hello                   </span></span>", tooltip)
+		);
+		assert_eq!(parcel.html_line(1), format!("<span title=\"{}\">                        </span>", tooltip));
+		let plain = Parcel::from_text("3 4\nhello", Owner::user("troido")).unwrap();
+		assert_eq!(plain.tooltip(), None);
+		assert_eq!(plain.html_line(1), "                        ");
+	}
+
+	fn accessible(broken_links: &HashSet<String>) -> HtmlLineOptions<'_> {
+		HtmlLineOptions { broken_links, user_anchor: false, accessible: true }
+	}
+
+	#[test]
+	fn alt_text_and_link_order() {
+		let text = format!("#alt A red house\n3 4\n 2 1\n1 3{}\n-\n1 https://a\n2 https://b\n3 https://c\n4 https://d", "\n".repeat(10));
+		let parcel = Parcel::from_text(&text, Owner::user("troido")).unwrap();
+		assert_eq!(parcel.alt_text(), "A red house");
+		let keys: Vec<char> = parcel.links_in_order().into_iter().map(|(key, _)| key).collect();
+		assert_eq!(keys, vec!['2', '1', '3']);
+		assert_eq!(parcel.html_line_with(0, &accessible(&HashSet::new())), "<span role=\"img\" aria-label=\"A red house\"> </span><a href=\"https://b\" aria-label=\"https://b\">2</a><span aria-hidden=\"true\"> </span><a href=\"https://a\" aria-label=\"https://a\">1</a><span aria-hidden=\"true\">                    </span>");
+		assert_eq!(parcel.html_line_with(2, &accessible(&HashSet::new())), "<span aria-hidden=\"true\">                        </span>");
+		assert_eq!(Parcel::empty(Owner::user("troido"), Pos::new(-1, 2)).alt_text(), "Plot of ~troido at -1,2");
+		assert_eq!(Parcel::empty(Owner::Public, Pos::new(0, 0)).alt_text(), "Plot at 0,0");
+	}
+
+	#[test]
+	fn render_link_titles() {
+		let text = format!("3 4\n1 2{}\n-\n1 https://a \"Home <3\" rel=me\n2 https://b", "\n".repeat(11));
+		let parcel = Parcel::from_text(&text, Owner::Public).unwrap();
+		assert_eq!(parcel.links[&'1'].title.as_deref(), Some("Home <3"));
+		assert_eq!(
+			parcel.html_line(0),
+			"<a href=\"https://a\" title=\"Home &lt;3\" rel=\"me\">1</a> <a href=\"https://b\">2</a>                     "
+		);
+		// links are named by their title, or otherwise their url
+		assert_eq!(
+			parcel.html_line_with(0, &accessible(&HashSet::new())),
+			"<a href=\"https://a\" title=\"Home &lt;3\" rel=\"me\" aria-label=\"Home &lt;3\">1</a><span role=\"img\" aria-label=\"Plot at 3,4\"> </span><a href=\"https://b\" aria-label=\"https://b\">2</a><span aria-hidden=\"true\">                     </span>"
+		);
+		assert!(parcel.to_text().ends_with("-\n1 https://a \"Home <3\" rel=me\n2 https://b\n"));
+	}
+
+	#[test]
+	fn escape_attribute_names() {
+		let mut parcel = Parcel::from_text("3 4\n1", Owner::Public).unwrap();
+		let link = Link { attributes: std::collections::BTreeMap::from([("x\"><b".to_string(), "y".to_string())]), ..Link::new("https://a") };
+		parcel.links.insert('1', link);
+		assert!(parcel.html_line(0).starts_with("<a href=\"https://a\" x&quot;&gt;&lt;b=\"y\">1</a>"));
+	}
+
+	#[test]
+	fn render_inactive_parcel_faded() {
+		let parcel = Parcel { inactive: true, ..Parcel::from_text("3 4\nhello", Owner::user("troido")).unwrap() };
+		assert_eq!(parcel.html_line(0), "<span class=\"inactive\"><span id=\"troido\">hello                   </span></span>");
+		assert_eq!(parcel.html_line(1), "<span class=\"inactive\">                        </span>");
+	}
+
+	#[test]
+	fn parse_template() {
+		let parcel = Parcel::from_text(&template_text(Pos::new(3, -2), "troido", "https://tilde.town/~troido/"), Owner::user("troido")).unwrap();
+		assert_eq!(parcel.location, Pos::new(3, -2));
+		assert_eq!(parcel.art[0], "+----------------------+");
+		assert_eq!(parcel.art[1], "|                      |");
+		assert_eq!(parcel.art[10], "|              ~troido |");
+		assert_eq!(parcel.mask[10], "|              1111111 |");
+		assert_eq!(parcel.art[11], "+----------------------+");
+		assert_eq!(parcel.links, hashmap!('1' => Link::new("https://tilde.town/~troido/")));
+	}
+
 	#[test]
 	fn truncate_long_str() {
-		assert_eq!(process_plot_line("hello_world", 8), "hello_wo".to_string());
+		assert_eq!(plot_line("hello_world", 8), "hello_wo".to_string());
 	}
 
 	#[test]
 	fn pad_short_str() {
-		assert_eq!(process_plot_line("hi", 8), "hi      ".to_string());
+		assert_eq!(plot_line("hi", 8), "hi      ".to_string());
 	}
 
 	#[test]
 	fn truncate_unicode() {
-		assert_eq!(process_plot_line("║╒╔╕╗", 1), "║".to_string());
-		assert_eq!(process_plot_line("║╒╔╕╗", 2), "║╒".to_string());
-		assert_eq!(process_plot_line("║╒╔╕╗", 3), "║╒╔".to_string());
-		assert_eq!(process_plot_line("║╒╔╕╗", 4), "║╒╔╕".to_string());
-		assert_eq!(process_plot_line("║╒╔╕╗", 5), "║╒╔╕╗".to_string());
+		assert_eq!(plot_line("║╒╔╕╗", 1), "║".to_string());
+		assert_eq!(plot_line("║╒╔╕╗", 2), "║╒".to_string());
+		assert_eq!(plot_line("║╒╔╕╗", 3), "║╒╔".to_string());
+		assert_eq!(plot_line("║╒╔╕╗", 4), "║╒╔╕".to_string());
+		assert_eq!(plot_line("║╒╔╕╗", 5), "║╒╔╕╗".to_string());
 	}
 
 	#[test]
 	fn replace_disallowed_characters() {
-		assert_eq!(process_plot_line("|..👻.◫.|", 8), "|..?.?.|".to_string());
+		let mut replacements = Vec::new();
+		assert_eq!(process_plot_line("|..👻.◫.|", 8, &DEFAULT_CHARSET, 3, &mut replacements), "|..?.?.|".to_string());
+		assert_eq!(replacements, vec![
+			Replacement { row: 3, column: 3, ch: '👻', reason: ReplaceReason::NotAllowed },
+			Replacement { row: 3, column: 5, ch: '◫', reason: ReplaceReason::NotAllowed },
+		]);
+		assert_eq!(replacements[0].to_string(), "line 4, column 4: '👻' (U+1F47B) is not one of the allowed characters, so it is replaced with '?'");
+	}
+
+	#[test]
+	fn process_wide_and_zero_width_characters() {
+		let charset = Charset::from_chars("漢字aex".chars());
+		let mut replacements = Vec::new();
+		assert_eq!(process_plot_line("漢字a", 4, &charset, 0, &mut replacements), "漢字");
+		assert_eq!(process_plot_line("漢字a", 5, &charset, 0, &mut replacements), "漢字a");
+		assert_eq!(process_plot_line("a漢字", 4, &charset, 0, &mut replacements), "a漢 ");
+		assert!(replacements.is_empty());
+		assert_eq!(process_plot_line("e\u{301}x\u{200b}", 4, &charset, 2, &mut replacements), "ex  ");
+		assert_eq!(replacements, vec![
+			Replacement { row: 2, column: 1, ch: '\u{301}', reason: ReplaceReason::ZeroWidth },
+			Replacement { row: 2, column: 3, ch: '\u{200b}', reason: ReplaceReason::ZeroWidth },
+		]);
+		assert_eq!(line_cells("a漢b"), vec![Some('a'), Some('漢'), None, Some('b')]);
+	}
+
+	#[test]
+	fn link_wide_characters_by_cell() {
+		let mut parcel = Parcel::empty(Owner::Public, Pos::new(0, 0));
+		parcel.art[0] = "漢a".to_string();
+		parcel.mask[0] = "  1".to_string();
+		parcel.links.insert('1', Link::new("https://example.com"));
+		assert_eq!(parcel.html_line(0), "漢<a href=\"https://example.com\">a</a>");
+		assert_eq!(
+			parcel.html_line_with(0, &accessible(&HashSet::new())),
+			"<span role=\"img\" aria-label=\"Plot at 0,0\">漢</span><a href=\"https://example.com\" aria-label=\"https://example.com\">a</a>"
+		);
+		// without any decoration on the first line, the label gets a span of its own
+		parcel.art[0] = "ab".to_string();
+		parcel.mask[0] = "11".to_string();
+		assert_eq!(
+			parcel.html_line_with(0, &accessible(&HashSet::new())),
+			"<a href=\"https://example.com\" aria-label=\"https://example.com\">ab</a><span role=\"img\" aria-label=\"Plot at 0,0\"></span>"
+		);
 	}
 
 	#[test]
@@ -303,10 +948,17 @@ z"#;
 				"........................"
 			].map(String::from).to_vec(),
 			links: hashmap!(
-				'1' => "https://tilde.town/~troido/cadastre/".to_string(),
-				'2' => "https://tilde.town/~troido/index.html".to_string(),
-				'3' => "https://tilde.town/~troido/entrance.html".to_string()
-			)
+				'1' => Link::new("https://tilde.town/~troido/cadastre/"),
+				'2' => Link::new("https://tilde.town/~troido/index.html"),
+				'3' => Link::new("https://tilde.town/~troido/entrance.html")
+			),
+			title: None,
+			description: None,
+			tags: Vec::new(),
+			alt: None,
+			name: None,
+			inactive: false,
+			stale: 0
 		};
 		assert_eq!(Parcel::from_text(parceltext, Owner::user("troido")).unwrap(), expected);
 	}
@@ -361,8 +1013,15 @@ z"#;
 				"....................    "
 			].map(String::from).to_vec(),
 			links: hashmap!(
-				'#' => "https://example.com".to_string()
-			)
+				'#' => Link::new("https://example.com")
+			),
+			title: None,
+			description: None,
+			tags: Vec::new(),
+			alt: None,
+			name: None,
+			inactive: false,
+			stale: 0
 		};
 		assert_eq!(Parcel::from_text(parceltext, Owner::user("johndoe")).unwrap(), expected);
 	}
@@ -427,8 +1086,8 @@ z"#;
 "#;
 		let parcel: Parcel = Parcel::from_text(parceltext, Owner::user("troido")).unwrap();
 		assert_eq!(parcel.links, hashmap!(
-			'?' => "https://en.wikipedia.org".to_string(),
-			'!' => r#"javascript:(function(){ console.log("<hello> " + '"world"'); })()"#.to_string()
+			'?' => Link::new("https://en.wikipedia.org"),
+			'!' => Link::new(r#"javascript:(function(){ console.log("<hello> " + '"world"'); })()"#)
 		));
 		assert_eq!(parcel.html_line(6), r#"....<a href="javascript:(function(){ console.log(&quot;<hello> &quot; + '&quot;world&quot;'); })()">!!!!!!!!</a>............"#.to_string());
 	}
@@ -4,6 +4,7 @@ use serde::{Serialize, Serializer, Deserialize, Deserializer};
 use crate::strutil;
 
 
+/// A position: plot coordinates for parcels, or character coordinates in a render
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Default)]
 pub struct Pos {
 	pub x: i64,
@@ -0,0 +1,65 @@
+
+use crate::{
+	cadastre::Cadastre,
+	parcel::PLOT_HEIGHT,
+	pos::Pos
+};
+
+const HIGHLIGHT_START: &str = "\x1b[7m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+
+/// The text of a plot surrounded by its eight neighbours, one line of text per item
+/// With highlight, the border of the plot in the middle is shown in reverse video
+pub fn preview_lines(cadastre: &Cadastre, location: Pos, highlight: bool) -> Vec<String> {
+	let corner = location - Pos::new(1, 1);
+	let mut lines = Vec::with_capacity(3 * PLOT_HEIGHT);
+	for plot_y in corner.y..(corner.y + 3) {
+		for inner_y in 0..PLOT_HEIGHT {
+			let mut line = String::new();
+			for plot_x in corner.x..(corner.x + 3) {
+				let plot = Pos::new(plot_x, plot_y);
+				let plot_line = cadastre.plot_text_line(plot, inner_y);
+				if highlight && plot == location {
+					line.push_str(&highlight_border(&plot_line, inner_y));
+				} else {
+					line.push_str(&plot_line);
+				}
+			}
+			lines.push(line);
+		}
+	}
+	lines
+}
+
+/// Mark the border of a plot in reverse video: the whole first and last line, and the first and last character of the other lines
+fn highlight_border(line: &str, inner_y: usize) -> String {
+	if inner_y == 0 || inner_y == PLOT_HEIGHT - 1 {
+		return format!("{}{}{}", HIGHLIGHT_START, line, HIGHLIGHT_END);
+	}
+	// wide characters take two cells, so the last character is not always at PLOT_WIDTH - 1
+	let chars: Vec<char> = line.chars().collect();
+	let (first, middle, last) = (chars[0], &chars[1..chars.len() - 1], chars[chars.len() - 1]);
+	format!("{}{}{}{}{}{}{}", HIGHLIGHT_START, first, HIGHLIGHT_END, String::from_iter(middle), HIGHLIGHT_START, last, HIGHLIGHT_END)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Owner, Parcel, PLOT_WIDTH};
+
+	#[test]
+	fn highlight_plot_border() {
+		assert_eq!(highlight_border("+--+", 0), "\x1b[7m+--+\x1b[0m");
+		assert_eq!(highlight_border("|世 |", 1), "\x1b[7m|\x1b[0m世 \x1b[7m|\x1b[0m");
+	}
+
+	#[test]
+	fn preview_plot_between_neighbours() {
+		let town = Cadastre::empty().with_parcel(Parcel::from_text("3 4\nhello", Owner::user("troido")).unwrap());
+		let lines = preview_lines(&town, Pos::new(3, 4), true);
+		assert_eq!(lines.len(), 3 * PLOT_HEIGHT);
+		let middle = &lines[PLOT_HEIGHT];
+		assert!(middle.contains(&format!("{}hello{}{}", HIGHLIGHT_START, " ".repeat(PLOT_WIDTH - 5), HIGHLIGHT_END)));
+		assert!(!lines[0].contains(HIGHLIGHT_START));
+	}
+}
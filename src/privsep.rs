@@ -0,0 +1,440 @@
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use crate::safefile::{FileError, MAX_SYMLINKS};
+
+const STATUS_OK: u8 = 0;
+const STATUS_NOT_REGULAR: u8 = 1;
+const STATUS_TOO_LARGE: u8 = 2;
+const STATUS_WRONG_OWNER: u8 = 3;
+const STATUS_OS_ERROR: u8 = 4;
+const STATUS_OUTSIDE_HOME: u8 = 5;
+
+/// The most directories the child keeps open while walking a path, which limits how deep a path can go
+const MAX_DEPTH: usize = 256;
+/// The longest listing of a directory that is sent back, with the names separated by nul bytes
+const MAX_LISTING_SIZE: u64 = 1 << 16;
+
+/// What the child does with the file it opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+	/// Send the contents of a regular file owned by the owner of the home dir
+	Read,
+	/// Send the names in a directory
+	List
+}
+
+/// Read a file from a user's home dir in a child process that runs as the owner of that home dir
+/// This way the file can only be read if the user could read it themselves
+/// The child also resolves the path, so nothing in the home dir is looked at with the privileges of the parent
+pub fn read_home_file_as_owner(homedir: &Path, path: &Path, max_size: u64) -> Result<String, FileError> {
+	let body = run_in_home(homedir, path, Action::Read, max_size)?;
+	String::from_utf8(body).map_err(|err| FileError::Io(err.to_string()))
+}
+
+/// List a directory in a user's home dir in a child process that runs as the owner of that home dir
+/// The entries are returned as paths in the directory as it was given, so reading them later resolves them again
+pub fn list_home_dir_as_owner(homedir: &Path, dir: &Path) -> Result<Vec<PathBuf>, FileError> {
+	let body = run_in_home(homedir, dir, Action::List, MAX_LISTING_SIZE)?;
+	Ok(body.split(|byte| *byte == 0)
+		.filter(|name| !matches!(*name, b"" | b"." | b".."))
+		.map(|name| dir.join(OsString::from_vec(name.to_vec())))
+		.collect())
+}
+
+/// Only the home dir itself is resolved by the parent, the path in it is passed on to the child as it was given
+fn run_in_home(homedir: &Path, path: &Path, action: Action, max_size: u64) -> Result<Vec<u8>, FileError> {
+	let home = fs::canonicalize(homedir).map_err(FileError::from)?;
+	// `homedir` is the home dir as it was given and `home` its canonical path; paths can start with either
+	let relative = path.strip_prefix(homedir).or_else(|_| path.strip_prefix(&home))
+		.map_err(|_| FileError::OutsideHome(path.to_path_buf()))?;
+	let home_meta = fs::metadata(&home).map_err(FileError::from)?;
+	let home_file = fs::OpenOptions::new()
+		.read(true)
+		.custom_flags(libc::O_DIRECTORY | libc::O_NOFOLLOW)
+		.open(&home)
+		.map_err(FileError::from)?;
+	let mut walk = Walk::new(home_file.as_raw_fd(), homedir.as_os_str().as_bytes(), home.as_os_str().as_bytes(), relative.as_os_str().as_bytes())?;
+	let output = run_as(home_meta.uid(), home_meta.gid(), &mut walk, action, max_size)?;
+	let (status, body) = output.split_first().ok_or_else(|| FileError::Io("helper process exited without result".to_string()))?;
+	match *status {
+		STATUS_OK => Ok(body.to_vec()),
+		STATUS_NOT_REGULAR => Err(FileError::NotRegularFile),
+		STATUS_TOO_LARGE => Err(FileError::TooLarge(max_size)),
+		STATUS_WRONG_OWNER => Err(FileError::WrongOwner { expected: home_meta.uid(), found: decode_u32(body)? }),
+		STATUS_OS_ERROR => Err(FileError::from(std::io::Error::from_raw_os_error(decode_u32(body)? as i32))),
+		// a path that leaves the home dir with '..' has no symlink target to show
+		STATUS_OUTSIDE_HOME if body.is_empty() => Err(FileError::OutsideHome(path.to_path_buf())),
+		STATUS_OUTSIDE_HOME => Err(FileError::OutsideHome(PathBuf::from(OsString::from_vec(body.to_vec())))),
+		_ => Err(FileError::Io("invalid result from helper process".to_string()))
+	}
+}
+
+fn decode_u32(bytes: &[u8]) -> Result<u32, FileError> {
+	Ok(u32::from_le_bytes(
+		bytes.try_into().map_err(|_| FileError::Io("invalid result from helper process".to_string()))?
+	))
+}
+
+/// Fork a child that drops to the given uid and gid, opens the path and writes the result back over a pipe
+/// The output starts with a status byte, followed by the file contents, the directory listing or the details of the error
+fn run_as(uid: u32, gid: u32, walk: &mut Walk, action: Action, max_size: u64) -> Result<Vec<u8>, FileError> {
+	// The child must not allocate, so the buffer for the result is allocated before forking
+	let mut buf: Vec<u8> = vec![0; max_size as usize + 1];
+	let mut fds = [0; 2];
+	if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+		return Err(FileError::from(std::io::Error::last_os_error()));
+	}
+	let [read_fd, write_fd] = fds;
+	let pid = unsafe { libc::fork() };
+	if pid < 0 {
+		let err = std::io::Error::last_os_error();
+		unsafe {
+			libc::close(read_fd);
+			libc::close(write_fd);
+		}
+		return Err(FileError::from(err));
+	}
+	if pid == 0 {
+		unsafe {
+			libc::close(read_fd);
+			child(uid, gid, walk, action, &mut buf, write_fd)
+		}
+	}
+	unsafe { libc::close(write_fd) };
+	let mut output = Vec::new();
+	let read_result = unsafe { fs::File::from_raw_fd(read_fd) }.read_to_end(&mut output);
+	let mut wait_status = 0;
+	unsafe { libc::waitpid(pid, &mut wait_status, 0) };
+	read_result.map_err(FileError::from)?;
+	Ok(output)
+}
+
+/// Only async-signal-safe functions may be used here, since the parent might have had other threads
+unsafe fn child(uid: u32, gid: u32, walk: &mut Walk, action: Action, buf: &mut [u8], out: libc::c_int) -> ! {
+	// Privileges only need to be dropped when not already running as the owner
+	if libc::geteuid() != uid && (libc::setgroups(0, std::ptr::null()) != 0 || libc::setgid(gid) != 0 || libc::setuid(uid) != 0) {
+		fail_errno(out);
+	}
+	let fd = match walk.open() {
+		Ok(fd) => fd,
+		Err(WalkError::Errno(errno)) => finish(out, STATUS_OS_ERROR, &(errno as u32).to_le_bytes()),
+		Err(WalkError::OutsideHome(len)) => finish(out, STATUS_OUTSIDE_HOME, &walk.link[..len])
+	};
+	let mut stat: libc::stat = std::mem::zeroed();
+	if libc::fstat(fd, &mut stat) != 0 {
+		fail_errno(out);
+	}
+	match action {
+		Action::Read => read_file(fd, &stat, uid, buf, out),
+		Action::List => list_dir(fd, &stat, &mut walk.entries, buf, out)
+	}
+}
+
+unsafe fn read_file(fd: libc::c_int, stat: &libc::stat, uid: u32, buf: &mut [u8], out: libc::c_int) -> ! {
+	if stat.st_mode & libc::S_IFMT != libc::S_IFREG {
+		finish(out, STATUS_NOT_REGULAR, &[]);
+	}
+	if stat.st_uid != uid {
+		finish(out, STATUS_WRONG_OWNER, &stat.st_uid.to_le_bytes());
+	}
+	let mut len = 0;
+	while len < buf.len() {
+		let n = libc::read(fd, buf[len..].as_mut_ptr() as *mut libc::c_void, buf.len() - len);
+		if n < 0 {
+			fail_errno(out);
+		}
+		if n == 0 {
+			break;
+		}
+		len += n as usize;
+	}
+	if len == buf.len() {
+		finish(out, STATUS_TOO_LARGE, &[]);
+	}
+	finish(out, STATUS_OK, &buf[..len]);
+}
+
+/// The names are read with getdents64 directly, since opendir allocates
+unsafe fn list_dir(fd: libc::c_int, stat: &libc::stat, entries: &mut [u8], buf: &mut [u8], out: libc::c_int) -> ! {
+	if stat.st_mode & libc::S_IFMT != libc::S_IFDIR {
+		finish(out, STATUS_NOT_REGULAR, &[]);
+	}
+	let mut len = 0;
+	loop {
+		let n = libc::syscall(libc::SYS_getdents64, fd, entries.as_mut_ptr(), entries.len());
+		if n < 0 {
+			fail_errno(out);
+		}
+		if n == 0 {
+			break;
+		}
+		// each record is an inode (8 bytes), an offset (8), its own length (2), a type (1) and the nul-terminated name
+		let mut offset = 0;
+		while offset < n as usize {
+			let reclen = u16::from_ne_bytes([entries[offset + 16], entries[offset + 17]]) as usize;
+			let record = &entries[offset + 19..offset + reclen];
+			let name = &record[..record.iter().position(|byte| *byte == 0).unwrap_or(record.len())];
+			if len + name.len() + 1 >= buf.len() {
+				finish(out, STATUS_TOO_LARGE, &[]);
+			}
+			buf[len..len + name.len()].copy_from_slice(name);
+			buf[len + name.len()] = 0;
+			len += name.len() + 1;
+			offset += reclen;
+		}
+	}
+	finish(out, STATUS_OK, &buf[..len]);
+}
+
+enum WalkError {
+	Errno(i32),
+	/// The length of the symlink target in the link buffer that leaves the home dir, or 0 when the path itself leaves it
+	OutsideHome(usize)
+}
+
+/// Walks a path from the home dir in the child, the same way safefile opens files in a home dir
+/// Every component is opened with openat without letting the kernel follow symlinks, so the path can't be swapped while it is walked
+/// Symlinks are resolved here instead, and only as long as they stay inside the home dir
+/// All buffers are allocated before forking, since the child can't allocate
+struct Walk<'a> {
+	home_fd: libc::c_int,
+	homedir: &'a [u8],
+	home: &'a [u8],
+	/// The part of the path that is left to walk, kept at the end of the buffer so symlink targets can be put in front of it
+	rest: Vec<u8>,
+	start: usize,
+	link: Vec<u8>,
+	name: Vec<u8>,
+	/// Room for the records of getdents64 when listing a directory
+	entries: Vec<u8>
+}
+
+impl<'a> Walk<'a> {
+	fn new(home_fd: libc::c_int, homedir: &'a [u8], home: &'a [u8], relative: &[u8]) -> Result<Self, FileError> {
+		let mut rest = vec![0; 2 * libc::PATH_MAX as usize];
+		if relative.len() > rest.len() {
+			return Err(FileError::from(std::io::Error::from_raw_os_error(libc::ENAMETOOLONG)));
+		}
+		let start = rest.len() - relative.len();
+		rest[start..].copy_from_slice(relative);
+		Ok(Self {
+			home_fd,
+			homedir,
+			home,
+			rest,
+			start,
+			link: vec![0; libc::PATH_MAX as usize],
+			name: vec![0; 256],
+			entries: vec![0; 1 << 15]
+		})
+	}
+
+	/// Open the path, returning the file descriptor of the file or directory it ends at
+	unsafe fn open(&mut self) -> Result<libc::c_int, WalkError> {
+		let Walk { home_fd, homedir, home, rest, start, link, name, .. } = self;
+		// the directories that have been walked into, starting with the home dir itself
+		let mut dirs = [0; MAX_DEPTH];
+		dirs[0] = libc::dup(*home_fd);
+		if dirs[0] < 0 {
+			return Err(WalkError::Errno(errno()));
+		}
+		let mut depth = 1;
+		let mut symlinks = 0;
+		loop {
+			while *start < rest.len() && rest[*start] == b'/' {
+				*start += 1;
+			}
+			if *start == rest.len() {
+				// the path ends at a directory
+				return Ok(dirs[depth - 1]);
+			}
+			let end = rest[*start..].iter().position(|byte| *byte == b'/').map_or(rest.len(), |i| *start + i);
+			let component = *start..end;
+			*start = end;
+			let is_last = rest[end..].iter().all(|byte| *byte == b'/');
+			match &rest[component.clone()] {
+				b"." => continue,
+				b".." => {
+					if depth == 1 {
+						return Err(WalkError::OutsideHome(0));
+					}
+					depth -= 1;
+					libc::close(dirs[depth]);
+					continue;
+				}
+				_ if component.len() >= name.len() => return Err(WalkError::Errno(libc::ENAMETOOLONG)),
+				component_name => {
+					name[..component_name.len()].copy_from_slice(component_name);
+					name[component_name.len()] = 0;
+				}
+			}
+			let dir = dirs[depth - 1];
+			let flags = if is_last { 0 } else { libc::O_DIRECTORY };
+			let fd = libc::openat(dir, name.as_ptr() as *const libc::c_char, libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_NONBLOCK | libc::O_CLOEXEC | flags);
+			if fd >= 0 {
+				if is_last {
+					return Ok(fd);
+				}
+				if depth == MAX_DEPTH {
+					return Err(WalkError::Errno(libc::ENAMETOOLONG));
+				}
+				dirs[depth] = fd;
+				depth += 1;
+				continue;
+			}
+			// with O_NOFOLLOW, opening a symlink fails with ELOOP, or ENOTDIR when a directory is required
+			let open_errno = errno();
+			if open_errno != libc::ELOOP && open_errno != libc::ENOTDIR {
+				return Err(WalkError::Errno(open_errno));
+			}
+			let len = libc::readlinkat(dir, name.as_ptr() as *const libc::c_char, link.as_mut_ptr() as *mut libc::c_char, link.len());
+			if len < 0 {
+				// not a symlink after all
+				let link_errno = errno();
+				return Err(WalkError::Errno(if link_errno == libc::EINVAL { open_errno } else { link_errno }));
+			}
+			symlinks += 1;
+			if symlinks > MAX_SYMLINKS {
+				return Err(WalkError::Errno(libc::ELOOP));
+			}
+			let mut target = &link[..len as usize];
+			if target.first() == Some(&b'/') {
+				target = match strip_dir(target, home).or_else(|| strip_dir(target, homedir)) {
+					Some(inside) => inside,
+					None => return Err(WalkError::OutsideHome(len as usize))
+				};
+				while depth > 1 {
+					depth -= 1;
+					libc::close(dirs[depth]);
+				}
+			}
+			if target.len() + 1 > *start {
+				return Err(WalkError::Errno(libc::ENAMETOOLONG));
+			}
+			*start -= 1;
+			rest[*start] = b'/';
+			*start -= target.len();
+			rest[*start..*start + target.len()].copy_from_slice(target);
+		}
+	}
+}
+
+/// The rest of a path after a directory, if the path is in that directory
+fn strip_dir<'p>(path: &'p [u8], dir: &[u8]) -> Option<&'p [u8]> {
+	let dir = dir.strip_suffix(b"/").unwrap_or(dir);
+	let rest = path.strip_prefix(dir)?;
+	(rest.is_empty() || rest[0] == b'/').then_some(rest)
+}
+
+unsafe fn errno() -> i32 {
+	*libc::__errno_location()
+}
+
+unsafe fn fail_errno(out: libc::c_int) -> ! {
+	finish(out, STATUS_OS_ERROR, &(errno() as u32).to_le_bytes());
+}
+
+unsafe fn finish(out: libc::c_int, status: u8, body: &[u8]) -> ! {
+	write_all(out, &[status]);
+	write_all(out, body);
+	libc::_exit(0);
+}
+
+unsafe fn write_all(out: libc::c_int, mut data: &[u8]) {
+	while !data.is_empty() {
+		let n = libc::write(out, data.as_ptr() as *const libc::c_void, data.len());
+		if n <= 0 {
+			return;
+		}
+		data = &data[n as usize..];
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::os::unix::fs::{chown, PermissionsExt};
+	use std::process::Command;
+	use crate::safefile::tests::test_dir;
+
+	#[test]
+	fn read_as_self() {
+		let dir = test_dir("privsep-self");
+		fs::write(dir.join("home.txt"), "1 2\nhello").unwrap();
+		assert_eq!(read_home_file_as_owner(&dir, &dir.join("home.txt"), 100), Ok("1 2\nhello".to_string()));
+		assert_eq!(read_home_file_as_owner(&dir, &dir.join("missing.txt"), 100), Err(FileError::NotFound));
+		assert_eq!(read_home_file_as_owner(&dir, &dir, 100), Err(FileError::NotRegularFile));
+		fs::write(dir.join("large.txt"), "x".repeat(101)).unwrap();
+		assert_eq!(read_home_file_as_owner(&dir, &dir.join("large.txt"), 100), Err(FileError::TooLarge(100)));
+	}
+
+	#[test]
+	fn resolve_symlinks_in_child() {
+		let dir = test_dir("privsep-symlinks");
+		let home = dir.join("home");
+		fs::create_dir_all(home.join("parcels")).unwrap();
+		fs::write(home.join("parcels/home.txt"), "1 2\nhello").unwrap();
+		fs::write(dir.join("outside.txt"), "1 2\nsecret").unwrap();
+		std::os::unix::fs::symlink("parcels/home.txt", home.join("relative.txt")).unwrap();
+		std::os::unix::fs::symlink(home.join("parcels"), home.join("absolute")).unwrap();
+		std::os::unix::fs::symlink(dir.join("outside.txt"), home.join("outside.txt")).unwrap();
+		std::os::unix::fs::symlink("../outside.txt", home.join("up.txt")).unwrap();
+		let read = |path: &str| read_home_file_as_owner(&home, &home.join(path), 100);
+		assert_eq!(read("relative.txt"), Ok("1 2\nhello".to_string()));
+		assert_eq!(read("absolute/home.txt"), Ok("1 2\nhello".to_string()));
+		assert_eq!(read("parcels/../relative.txt"), Ok("1 2\nhello".to_string()));
+		assert_eq!(read("outside.txt"), Err(FileError::OutsideHome(dir.join("outside.txt"))));
+		assert_eq!(read("up.txt"), Err(FileError::OutsideHome(home.join("up.txt"))));
+		assert_eq!(read("../outside.txt"), Err(FileError::OutsideHome(home.join("../outside.txt"))));
+	}
+
+	#[test]
+	fn list_in_child() {
+		let dir = test_dir("privsep-list");
+		fs::create_dir_all(dir.join("parcels")).unwrap();
+		fs::write(dir.join("parcels/garden.prcl"), "").unwrap();
+		fs::write(dir.join("parcels/shop.prcl"), "").unwrap();
+		std::os::unix::fs::symlink("parcels", dir.join("link")).unwrap();
+		let mut listed = list_home_dir_as_owner(&dir, &dir.join("link")).unwrap();
+		listed.sort();
+		assert_eq!(listed, vec![dir.join("link/garden.prcl"), dir.join("link/shop.prcl")]);
+		assert_eq!(list_home_dir_as_owner(&dir, &dir.join("missing")), Err(FileError::NotFound));
+		assert_eq!(list_home_dir_as_owner(&dir, &dir.join("parcels/shop.prcl")), Err(FileError::NotRegularFile));
+	}
+
+	#[test]
+	#[ignore = "needs root to create a throwaway user"]
+	fn read_as_throwaway_user() {
+		let name = format!("cadastrs{}", std::process::id() % 100000);
+		let dir = test_dir("privsep-user");
+		fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+		let home = dir.join(&name);
+		assert!(Command::new("useradd").args(["--no-create-home", "--home-dir"]).arg(&home).arg(&name).status().unwrap().success());
+		let uid: u32 = String::from_utf8(Command::new("id").args(["-u", &name]).output().unwrap().stdout).unwrap().trim().parse().unwrap();
+
+		fs::create_dir(&home).unwrap();
+		chown(&home, Some(uid), Some(uid)).unwrap();
+		fs::write(home.join("home.txt"), "1 2\nhello").unwrap();
+		chown(home.join("home.txt"), Some(uid), Some(uid)).unwrap();
+		// a directory the user can't enter, containing a file that is owned by the user
+		fs::create_dir(home.join("locked")).unwrap();
+		fs::set_permissions(home.join("locked"), fs::Permissions::from_mode(0o700)).unwrap();
+		fs::write(home.join("locked").join("home.txt"), "1 2\nsecret").unwrap();
+		chown(home.join("locked").join("home.txt"), Some(uid), Some(uid)).unwrap();
+
+		let readable = read_home_file_as_owner(&home, &home.join("home.txt"), 100);
+		let locked = read_home_file_as_owner(&home, &home.join("locked").join("home.txt"), 100);
+		let locked_list = list_home_dir_as_owner(&home, &home.join("locked"));
+		Command::new("userdel").arg(&name).status().unwrap();
+
+		assert_eq!(readable, Ok("1 2\nhello".to_string()));
+		assert!(matches!(locked, Err(FileError::Io(_))), "{:?}", locked);
+		assert!(matches!(locked_list, Err(FileError::Io(_))), "{:?}", locked_list);
+	}
+}
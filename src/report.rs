@@ -0,0 +1,101 @@
+
+use std::fmt;
+use std::path::PathBuf;
+use crate::{
+	pos::Pos,
+	owner::Owner,
+	safefile::FileError,
+	timeout::Skipped,
+	parcel::Replacement
+};
+
+/// Everything noteworthy that happened during an update that the admins might want to review
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+	pub entries: Vec<ReportEntry>
+}
+
+impl Report {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn add(&mut self, owner: Owner, location: Option<Pos>, kind: ReportKind) {
+		self.entries.push(ReportEntry { owner, location, kind });
+	}
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for entry in &self.entries {
+			writeln!(f, "{}", entry)?;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportEntry {
+	pub owner: Owner,
+	pub location: Option<Pos>,
+	pub kind: ReportKind
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportKind {
+	ReadError(String),
+	ParseError(String),
+	ReplacedCharacters(PathBuf, Vec<Replacement>),
+	UnsafeFile(PathBuf, FileError),
+	Skipped(PathBuf, Skipped),
+	Retained,
+	Stale(u32),
+	StaleExpired(u32),
+	Denied,
+	NotAllowed,
+	QuotaExceeded(usize),
+	UnconfirmedCoOwner(String),
+	Inactive(u64),
+	Expired(u64),
+	UnknownActivity,
+	InvalidTransfer(String),
+	Transferred(String),
+	LostConflict(Owner),
+	BrokenLink(String, String)
+}
+
+impl fmt::Display for ReportEntry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:?}", self.owner)?;
+		if let Some(pos) = self.location {
+			write!(f, " at {},{}", pos.x, pos.y)?;
+		}
+		match &self.kind {
+			ReportKind::ReadError(err) => write!(f, ": can't read parcel: {}", err),
+			ReportKind::ParseError(err) => write!(f, ": failed parsing parcel:\n{}", err),
+			ReportKind::ReplacedCharacters(path, replacements) => {
+				write!(f, ": {} character(s) in {:?} can't be used:", replacements.len(), path)?;
+				for replacement in replacements {
+					write!(f, "\n  {}", replacement)?;
+				}
+				Ok(())
+			}
+			ReportKind::UnsafeFile(path, err) => write!(f, ": refused to read {:?}: {}", path, err),
+			ReportKind::Skipped(path, skipped) => write!(f, ": skipped {:?}: {}", path, skipped),
+			ReportKind::Retained => write!(f, ": kept the previous parcel"),
+			ReportKind::Stale(runs) => write!(f, ": kept the previous parcel because the new one is broken ({} update(s) in a row)", runs),
+			ReportKind::StaleExpired(runs) => write!(f, ": removed the previous parcel because the parcel file has been broken for {} updates", runs),
+			ReportKind::Denied => write!(f, ": rejected because the user is on the deny list"),
+			ReportKind::NotAllowed => write!(f, ": rejected because the user is not on the allow list"),
+			ReportKind::QuotaExceeded(quota) => write!(f, ": rejected because the owner already has {} plot(s)", quota),
+			ReportKind::UnconfirmedCoOwner(name) => write!(f, ": {} is not added as co-owner because they did not confirm", name),
+			ReportKind::Inactive(days) => write!(f, ": marked inactive after {} days without activity", days),
+			ReportKind::Expired(days) => write!(f, ": removed after {} days without activity", days),
+			ReportKind::UnknownActivity => write!(f, ": kept as active because the last activity could not be determined"),
+			ReportKind::InvalidTransfer(line) => write!(f, ": invalid transfer line \"{}\", expected a username optionally followed by a position", line),
+			ReportKind::Transferred(from) => write!(f, ": received plot from {}", from),
+			ReportKind::LostConflict(winner) => write!(f, ": rejected because the plot is claimed by {:?}", winner),
+			ReportKind::BrokenLink(url, reason) => write!(f, ": broken link to {}: {}", url, reason)
+		}
+	}
+}
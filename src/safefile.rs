@@ -0,0 +1,299 @@
+
+use std::collections::VecDeque;
+use std::ffi::{CString, OsString};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, ErrorKind};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+
+/// The maximum number of symlinks followed when opening a file in a home dir, like the limit of the kernel
+pub(crate) const MAX_SYMLINKS: usize = 40;
+
+/// Read a text file that is controlled by a user, from within that user's home dir
+/// Symlinks are only followed as long as they stay inside the home dir, and the file must be owned by the owner of the home dir
+pub fn read_home_file(homedir: &Path, path: &Path, max_size: u64) -> Result<String, FileError> {
+	let home = fs::canonicalize(homedir).map_err(FileError::from)?;
+	let file = open_in_home(homedir, &home, path)?;
+	let expected = fs::metadata(&home).map_err(FileError::from)?.uid();
+	let found = file.metadata().map_err(FileError::from)?.uid();
+	if found != expected {
+		return Err(FileError::WrongOwner { expected, found });
+	}
+	read_limited(file, max_size)
+}
+
+/// Read a text file that is not in a user's home dir, such as admin or public parcels
+/// The file must still be a regular file of limited size
+pub fn read_file(path: &Path, max_size: u64) -> Result<String, FileError> {
+	read_limited(open_nofollow(&fs::canonicalize(path).map_err(FileError::from)?)?, max_size)
+}
+
+/// Open a file in a home dir without ever leaving the home dir, even when the user replaces directories while it is opened
+/// The path is walked one component at a time with openat from the home dir, without letting the kernel follow symlinks
+/// Symlinks are resolved here instead, and only as long as they stay inside the home dir
+/// `homedir` is the home dir as it was given and `home` its canonical path; paths can start with either
+fn open_in_home(homedir: &Path, home: &Path, path: &Path) -> Result<File, FileError> {
+	let relative = path.strip_prefix(homedir).or_else(|_| path.strip_prefix(home))
+		.map_err(|_| FileError::OutsideHome(path.to_path_buf()))?;
+	let mut remaining: VecDeque<Step> = steps(relative, path)?.into();
+	// the directories that have been walked into, starting with the home dir itself
+	let mut dirs: Vec<OwnedFd> = vec![open_at(None, home.as_os_str(), libc::O_DIRECTORY)?];
+	let mut current = home.to_path_buf();
+	let mut symlinks = 0;
+	while let Some(step) = remaining.pop_front() {
+		let name = match step {
+			Step::Parent => {
+				if dirs.len() == 1 {
+					return Err(FileError::OutsideHome(current.join("..")));
+				}
+				dirs.pop();
+				current.pop();
+				continue;
+			}
+			Step::Name(name) => name
+		};
+		let is_last = remaining.is_empty();
+		let dir = dirs.last().expect("the home dir is never popped");
+		let flags = if is_last { 0 } else { libc::O_DIRECTORY };
+		match open_at(Some(dir), &name, flags) {
+			Ok(fd) if is_last => return Ok(File::from(fd)),
+			Ok(fd) => {
+				dirs.push(fd);
+				current.push(&name);
+			}
+			// with O_NOFOLLOW, opening a symlink fails with ELOOP, or ENOTDIR when a directory is required
+			Err(err) if matches!(err.raw_os_error(), Some(libc::ELOOP) | Some(libc::ENOTDIR)) => {
+				let target = match read_link_at(dir, &name) {
+					Ok(target) => target,
+					// not a symlink after all
+					Err(link_err) if link_err.raw_os_error() == Some(libc::EINVAL) => return Err(FileError::from(err)),
+					Err(link_err) => return Err(FileError::from(link_err))
+				};
+				symlinks += 1;
+				if symlinks > MAX_SYMLINKS {
+					return Err(FileError::from(err));
+				}
+				let target_steps = if target.is_absolute() {
+					let inside = target.strip_prefix(home).or_else(|_| target.strip_prefix(homedir))
+						.map_err(|_| FileError::OutsideHome(target.clone()))?;
+					let inside_steps = steps(inside, &target)?;
+					dirs.truncate(1);
+					current = home.to_path_buf();
+					inside_steps
+				} else {
+					steps(&target, &target)?
+				};
+				for step in target_steps.into_iter().rev() {
+					remaining.push_front(step);
+				}
+			}
+			Err(err) => return Err(FileError::from(err))
+		}
+	}
+	// the path was the home dir itself
+	Ok(File::from(dirs.swap_remove(0)))
+}
+
+/// One step of walking a path relative to a directory
+enum Step {
+	Parent,
+	Name(OsString)
+}
+
+fn steps(relative: &Path, path: &Path) -> Result<Vec<Step>, FileError> {
+	let mut steps = Vec::new();
+	for component in relative.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => steps.push(Step::Parent),
+			Component::Normal(name) => steps.push(Step::Name(name.to_os_string())),
+			Component::RootDir | Component::Prefix(_) => return Err(FileError::OutsideHome(path.to_path_buf()))
+		}
+	}
+	Ok(steps)
+}
+
+/// Open a name in a directory without following symlinks, or an absolute path when there is no directory
+fn open_at(dir: Option<&OwnedFd>, name: &std::ffi::OsStr, flags: libc::c_int) -> io::Result<OwnedFd> {
+	let cname = CString::new(name.as_bytes())?;
+	let dirfd = dir.map(AsRawFd::as_raw_fd).unwrap_or(libc::AT_FDCWD);
+	let fd = unsafe { libc::openat(dirfd, cname.as_ptr(), libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_NONBLOCK | libc::O_CLOEXEC | flags) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn read_link_at(dir: &OwnedFd, name: &OsString) -> io::Result<PathBuf> {
+	let cname = CString::new(name.as_bytes())?;
+	let mut buf = vec![0u8; libc::PATH_MAX as usize];
+	let len = unsafe { libc::readlinkat(dir.as_raw_fd(), cname.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+	if len < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	buf.truncate(len as usize);
+	Ok(PathBuf::from(OsString::from_vec(buf)))
+}
+
+fn open_nofollow(path: &Path) -> Result<File, FileError> {
+	// O_NONBLOCK makes sure opening a fifo doesn't wait for a writer
+	// O_NOFOLLOW makes sure the last component of the path wasn't replaced by a symlink after it was resolved
+	OpenOptions::new()
+		.read(true)
+		.custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+		.open(path)
+		.map_err(FileError::from)
+}
+
+fn read_limited(file: File, max_size: u64) -> Result<String, FileError> {
+	let meta = file.metadata().map_err(FileError::from)?;
+	if !meta.is_file() {
+		return Err(FileError::NotRegularFile);
+	}
+	if meta.len() > max_size {
+		return Err(FileError::TooLarge(max_size));
+	}
+	// the file can still grow after checking the metadata
+	let mut text = String::new();
+	file.take(max_size + 1).read_to_string(&mut text).map_err(FileError::from)?;
+	if text.len() as u64 > max_size {
+		return Err(FileError::TooLarge(max_size));
+	}
+	Ok(text)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileError {
+	NotFound,
+	OutsideHome(PathBuf),
+	NotRegularFile,
+	WrongOwner { expected: u32, found: u32 },
+	TooLarge(u64),
+	Io(String)
+}
+
+impl From<io::Error> for FileError {
+	fn from(err: io::Error) -> Self {
+		if err.kind() == ErrorKind::NotFound {
+			Self::NotFound
+		} else {
+			Self::Io(err.to_string())
+		}
+	}
+}
+
+impl fmt::Display for FileError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::NotFound => write!(f, "file not found"),
+			Self::OutsideHome(path) => write!(f, "file links to {:?}, outside of the home dir", path),
+			Self::NotRegularFile => write!(f, "not a regular file"),
+			Self::WrongOwner { expected, found } => write!(f, "file is owned by uid {} instead of uid {}", found, expected),
+			Self::TooLarge(max_size) => write!(f, "file is larger than {} bytes", max_size),
+			Self::Io(err) => write!(f, "{}", err)
+		}
+	}
+}
+impl std::error::Error for FileError {}
+
+#[cfg(test)]
+pub mod tests {
+	use super::*;
+	use std::ffi::CString;
+	use std::os::unix::ffi::OsStrExt;
+	use std::os::unix::fs::symlink;
+
+	/// A fresh directory for a single test
+	pub fn test_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("cadastrs-test-{}-{}", std::process::id(), name));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	pub fn make_fifo(path: &Path) {
+		let cpath = CString::new(path.as_os_str().as_bytes()).unwrap();
+		assert_eq!(unsafe { libc::mkfifo(cpath.as_ptr(), 0o600) }, 0);
+	}
+
+	#[test]
+	fn read_regular_file() {
+		let dir = test_dir("regular");
+		fs::write(dir.join("home.txt"), "1 2\nhello").unwrap();
+		assert_eq!(read_home_file(&dir, &dir.join("home.txt"), 100), Ok("1 2\nhello".to_string()));
+		assert_eq!(read_home_file(&dir, &dir.join("missing.txt"), 100), Err(FileError::NotFound));
+		assert_eq!(read_file(&dir.join("home.txt"), 100), Ok("1 2\nhello".to_string()));
+	}
+
+	#[test]
+	fn follow_symlinks_within_home() {
+		let dir = test_dir("inner-symlink");
+		fs::write(dir.join("art.txt"), "1 2\nhello").unwrap();
+		symlink(dir.join("art.txt"), dir.join("home.txt")).unwrap();
+		assert_eq!(read_home_file(&dir, &dir.join("home.txt"), 100), Ok("1 2\nhello".to_string()));
+	}
+
+	#[test]
+	fn reject_symlinks_leaving_home() {
+		let dir = test_dir("outer-symlink");
+		fs::create_dir(dir.join("home")).unwrap();
+		fs::write(dir.join("secret"), "hunter2").unwrap();
+		symlink(dir.join("secret"), dir.join("home").join("home.txt")).unwrap();
+		assert!(matches!(read_home_file(&dir.join("home"), &dir.join("home").join("home.txt"), 100), Err(FileError::OutsideHome(_))));
+	}
+
+	#[test]
+	fn reject_directory_symlinks_leaving_home() {
+		let dir = test_dir("outer-dir-symlink");
+		fs::create_dir(dir.join("home")).unwrap();
+		fs::create_dir(dir.join("other")).unwrap();
+		fs::write(dir.join("other").join("home.txt"), "hunter2").unwrap();
+		symlink(dir.join("other"), dir.join("home").join("public")).unwrap();
+		symlink("../../other", dir.join("home").join("relative")).unwrap();
+		let home = dir.join("home");
+		assert!(matches!(read_home_file(&home, &home.join("public").join("home.txt"), 100), Err(FileError::OutsideHome(_))));
+		assert!(matches!(read_home_file(&home, &home.join("relative").join("home.txt"), 100), Err(FileError::OutsideHome(_))));
+		assert!(matches!(read_home_file(&home, &home.join("..").join("other").join("home.txt"), 100), Err(FileError::OutsideHome(_))));
+	}
+
+	#[test]
+	fn follow_relative_symlinks_within_home() {
+		let dir = test_dir("relative-symlink");
+		fs::create_dir_all(dir.join("art").join("old")).unwrap();
+		fs::write(dir.join("art").join("town.txt"), "1 2\nhello").unwrap();
+		symlink("../town.txt", dir.join("art").join("old").join("town.txt")).unwrap();
+		symlink("art/old", dir.join("link")).unwrap();
+		assert_eq!(read_home_file(&dir, &dir.join("link").join("town.txt"), 100), Ok("1 2\nhello".to_string()));
+	}
+
+	#[test]
+	fn reject_files_of_other_users() {
+		// only root can give a file away
+		if unsafe { libc::geteuid() } != 0 {
+			return;
+		}
+		let dir = test_dir("other-owner");
+		fs::write(dir.join("home.txt"), "1 2\nhello").unwrap();
+		std::os::unix::fs::chown(dir.join("home.txt"), Some(12345), None).unwrap();
+		assert_eq!(read_home_file(&dir, &dir.join("home.txt"), 100), Err(FileError::WrongOwner { expected: 0, found: 12345 }));
+	}
+
+	#[test]
+	fn reject_special_files() {
+		let dir = test_dir("special");
+		make_fifo(&dir.join("home.txt"));
+		assert_eq!(read_home_file(&dir, &dir.join("home.txt"), 100), Err(FileError::NotRegularFile));
+		assert_eq!(read_home_file(&dir, &dir, 100), Err(FileError::NotRegularFile));
+	}
+
+	#[test]
+	fn reject_large_files() {
+		let dir = test_dir("large");
+		fs::write(dir.join("home.txt"), "x".repeat(101)).unwrap();
+		assert_eq!(read_home_file(&dir, &dir.join("home.txt"), 100), Err(FileError::TooLarge(100)));
+		assert_eq!(read_file(&dir.join("home.txt"), 100), Err(FileError::TooLarge(100)));
+	}
+}
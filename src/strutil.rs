@@ -9,7 +9,7 @@ pub fn split_once_whitespace(txt: &str) -> Option<(&str, &str)> {
 pub fn to_char(txt: &str) -> Option<char> {
 	let mut chars = txt.chars();
 	let ch = chars.next()?;
-	if chars.next() == None {
+	if chars.next().is_none() {
 		Some(ch)
 	} else {
 		None
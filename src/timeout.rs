@@ -0,0 +1,100 @@
+
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits on how long reading from slow or hostile sources can take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+	/// The maximum time to wait for a single source
+	pub read_timeout: Duration,
+	/// After this moment no more sources are read
+	pub deadline: Option<Instant>
+}
+
+impl Limits {
+	/// Run a blocking operation in a separate thread and give up waiting on it when it takes too long
+	/// An operation that hangs forever will leave its thread behind, but the rest of the update can continue
+	pub fn run<T, F>(&self, operation: F) -> Result<T, Skipped>
+			where T: Send + 'static, F: FnOnce() -> T + Send + 'static {
+		let mut timeout = self.read_timeout;
+		if let Some(deadline) = self.deadline {
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			if remaining.is_zero() {
+				return Err(Skipped::DeadlineReached);
+			}
+			timeout = timeout.min(remaining);
+		}
+		let (sender, receiver) = mpsc::channel();
+		thread::spawn(move || {
+			// the receiver is gone when the operation took too long, so the result is not needed anymore
+			let _ = sender.send(operation());
+		});
+		match receiver.recv_timeout(timeout) {
+			Ok(result) => Ok(result),
+			Err(RecvTimeoutError::Timeout) if timeout < self.read_timeout => Err(Skipped::DeadlineReached),
+			Err(RecvTimeoutError::Timeout) => Err(Skipped::TimedOut(self.read_timeout)),
+			// the operation panicked, which only affects the source it was reading
+			Err(RecvTimeoutError::Disconnected) => Err(Skipped::Failed)
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skipped {
+	TimedOut(Duration),
+	DeadlineReached,
+	/// The operation stopped without a result
+	Failed
+}
+
+impl fmt::Display for Skipped {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::TimedOut(timeout) => write!(f, "timed out after {} seconds", timeout.as_secs_f64()),
+			Self::DeadlineReached => write!(f, "the deadline for the update has been reached"),
+			Self::Failed => write!(f, "reading failed unexpectedly")
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use crate::safefile::tests::{test_dir, make_fifo};
+
+	#[test]
+	fn return_result_in_time() {
+		let limits = Limits { read_timeout: Duration::from_secs(10), deadline: None };
+		assert_eq!(limits.run(|| 3), Ok(3));
+	}
+
+	#[test]
+	fn skip_fifo_without_writer() {
+		let dir = test_dir("timeout-fifo");
+		let fifo = dir.join("home.txt");
+		make_fifo(&fifo);
+		let limits = Limits { read_timeout: Duration::from_millis(50), deadline: None };
+		assert_eq!(limits.run(move || fs::read_to_string(fifo).is_ok()), Err(Skipped::TimedOut(Duration::from_millis(50))));
+	}
+
+	#[test]
+	fn skip_when_operation_panics() {
+		let limits = Limits { read_timeout: Duration::from_secs(10), deadline: None };
+		assert_eq!(limits.run(|| -> u32 { panic!("hostile file") }), Err(Skipped::Failed));
+	}
+
+	#[test]
+	fn skip_after_deadline() {
+		let limits = Limits { read_timeout: Duration::from_secs(10), deadline: Some(Instant::now()) };
+		assert_eq!(limits.run(|| 3), Err(Skipped::DeadlineReached));
+
+		let dir = test_dir("timeout-deadline");
+		let fifo = dir.join("home.txt");
+		make_fifo(&fifo);
+		let limits = Limits { read_timeout: Duration::from_secs(10), deadline: Some(Instant::now() + Duration::from_millis(50)) };
+		assert_eq!(limits.run(move || fs::read_to_string(fifo).is_ok()), Err(Skipped::DeadlineReached));
+	}
+}
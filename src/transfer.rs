@@ -0,0 +1,71 @@
+
+use crate::{
+	pos::Pos,
+	parcel::Parcel,
+	strutil
+};
+
+/// A request by a user to hand over their plot to another user
+/// The transfer is completed when the recipient claims the same location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+	pub from: String,
+	pub to: String,
+	/// When no location is given, all plots of the giving user can be transferred
+	pub location: Option<Pos>
+}
+
+impl Transfer {
+	/// Each non-empty line of a transfer file has the name of the recipient, optionally followed by the location of the plot
+	/// Only one line can leave out the location, since otherwise it's not clear who gets the plots
+	/// A line with a location takes precedence over the line without one for that plot
+	/// On error, the offending line is returned
+	pub fn from_text(from: &str, text: &str) -> Result<Vec<Self>, String> {
+		let transfers: Vec<Self> = text.lines()
+			.map(str::trim)
+			.filter(|line| !line.is_empty())
+			.map(|line| {
+				let (to, location) = match strutil::split_once_whitespace(line) {
+					Some((to, pos)) => (to, Some(Pos::from_space_separated(pos).ok_or_else(|| line.to_string())?)),
+					None => (line, None)
+				};
+				// giving a plot to yourself would complete the transfer again on every update
+				if to == from {
+					return Err(line.to_string());
+				}
+				Ok(Self { from: from.to_string(), to: to.to_string(), location })
+			})
+			.collect::<Result<_, _>>()?;
+		if let Some(second) = transfers.iter().filter(|transfer| transfer.location.is_none()).nth(1) {
+			return Err(second.to.clone());
+		}
+		Ok(transfers)
+	}
+
+	/// Whether the previous parcel was held by the giving user and is covered by this transfer
+	pub fn covers(&self, held: &Parcel) -> bool {
+		held.owner.quota_holder() == Some(self.from.as_str())
+			&& self.location.is_none_or(|location| location == held.location)
+	}
+
+	/// Whether the parcel is a claim by the recipient
+	pub fn is_claimed_by(&self, parcel: &Parcel) -> bool {
+		parcel.owner.quota_holder() == Some(self.to.as_str())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_transfers() {
+		assert_eq!(Transfer::from_text("troido", "odiort\n\n  john 3 -4 \n").unwrap(), vec![
+			Transfer { from: "troido".to_string(), to: "odiort".to_string(), location: None },
+			Transfer { from: "troido".to_string(), to: "john".to_string(), location: Some(Pos::new(3, -4)) },
+		]);
+		assert_eq!(Transfer::from_text("troido", "odiort 3").unwrap_err(), "odiort 3");
+		assert_eq!(Transfer::from_text("troido", "odiort\ntroido 1 2").unwrap_err(), "troido 1 2");
+		assert_eq!(Transfer::from_text("troido", "odiort\njohn 1 2\njohn").unwrap_err(), "john");
+	}
+}
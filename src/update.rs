@@ -0,0 +1,539 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use crate::{
+	cadastre::{Cadastre, BuildRules},
+	parcel::{Parcel, ParseError},
+	charset::Charset,
+	owner::Owner,
+	report::{Report, ReportKind},
+	transfer::Transfer,
+	safefile::{self, FileError},
+	privsep,
+	timeout::{Limits, Skipped},
+};
+
+/// Where the parcels of an update are read from, and the rules for reading them
+/// The defaults are the same as those of the `cadastrs update` command
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateOptions {
+	/// The directory containing the home dirs of all users
+	pub homedirs: PathBuf,
+	/// The location of a user's parcel within their own home dir
+	pub parcel_in_home: PathBuf,
+	/// The directory within a user's own home dir where any additional parcels (ending in .prcl) are placed
+	pub parcel_dir_in_home: PathBuf,
+	/// The file within a user's own home dir that lists the users whose shared parcels they agree to co-own
+	pub share_confirmation_in_home: PathBuf,
+	/// The file within a user's own home dir that names the users to transfer their plots to
+	pub transfer_in_home: PathBuf,
+	/// The parcel files of the admins
+	pub admin_parcel: Vec<PathBuf>,
+	/// The directories for public parcels
+	pub public_parcels: Vec<PathBuf>,
+	/// Users whose parcels are never read
+	pub denied_users: Vec<String>,
+	/// If any users are given, only the parcels of these users are read
+	pub allowed_users: Vec<String>,
+	/// The maximum number of plots that a single user can hold, including additional parcels
+	pub plot_quota: usize,
+	/// Users are inactive if neither their parcel file nor their home dir has been modified for this many days
+	pub inactive_after_days: Option<u64>,
+	pub inactive_policy: InactivePolicy,
+	/// The maximum size in bytes of any file that is read
+	pub max_file_size: u64,
+	/// The characters that may be used in parcels
+	pub charset: Charset,
+	/// Read files in home dirs from a helper process that runs as the owner of the home dir
+	pub drop_privileges: bool,
+	/// The maximum time to wait for reading a single file or directory
+	pub read_timeout: Duration,
+	/// The maximum time that reading all parcels can take; any files after that are skipped
+	pub run_deadline: Option<Duration>,
+	/// Keep the previous parcels of a user when their files are skipped because they took too long
+	pub keep_on_timeout: bool,
+	/// When a user's parcel file fails to parse, keep their previous parcel for at most this many updates
+	pub sticky_runs: Option<u32>
+}
+
+impl Default for UpdateOptions {
+	fn default() -> Self {
+		Self {
+			homedirs: PathBuf::from("/home/"),
+			parcel_in_home: PathBuf::from(".cadastre/home.txt"),
+			parcel_dir_in_home: PathBuf::from(".cadastre/"),
+			share_confirmation_in_home: PathBuf::from(".cadastre/shared.txt"),
+			transfer_in_home: PathBuf::from(".cadastre/transfer.txt"),
+			admin_parcel: Vec::new(),
+			public_parcels: Vec::new(),
+			denied_users: Vec::new(),
+			allowed_users: Vec::new(),
+			plot_quota: 1,
+			inactive_after_days: None,
+			inactive_policy: InactivePolicy::Mark,
+			max_file_size: 65536,
+			charset: Charset::default(),
+			drop_privileges: false,
+			read_timeout: Duration::from_secs(10),
+			run_deadline: None,
+			keep_on_timeout: false,
+			sticky_runs: None
+		}
+	}
+}
+
+/// What to do with the parcels of inactive users
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum InactivePolicy {
+	/// keep the parcel, but let active users claim the plot and render it faded
+	Mark,
+	/// remove the parcel from the town
+	Drop
+}
+
+/// Read all parcels from the admin parcel files, the home dirs of the users and the public parcel directories, and build a new cadastre from them
+/// Everything that went wrong or that the admins should know about is added to the report
+pub fn generate_cadastre(options: &UpdateOptions, old: &Cadastre, report: &mut Report) -> Cadastre {
+	let limits = Limits {
+		read_timeout: options.read_timeout,
+		// a deadline too far away to represent is no deadline at all
+		deadline: options.run_deadline.and_then(|duration| Instant::now().checked_add(duration))
+	};
+	let charset = &options.charset;
+	let mut parcels: Vec<Parcel> = Vec::new();
+
+	for path in &options.admin_parcel {
+		parcels.extend(read_parcel(options, &limits, charset, path, None, Owner::Admin, report).ok().flatten());
+	}
+
+	let mut transfers: Vec<Transfer> = Vec::new();
+
+	let homedirs = fs::read_dir(&options.homedirs).expect("Failed to find home directories")
+		.filter_map(Result::ok)
+		.map(|entry| entry.path());
+	for homedir in homedirs {
+		let Some(owner) = Owner::from_homedir(&homedir) else { continue };
+		let Owner::User(username) = &owner else { continue };
+		// the deny and allow lists are checked before anything in the home dir is touched
+		// so it's not known whether the user still has parcel files; the plots they held are reported instead
+		if let Some(rejection) = check_user_access(options, &owner) {
+			for parcel in old.parcels_by(username) {
+				report.add(parcel.owner.clone(), Some(parcel.location), rejection.clone());
+			}
+			continue;
+		}
+		let homepaths = {
+			let (homedir, parcel_in_home, parcel_dir_in_home) = (homedir.clone(), options.parcel_in_home.clone(), options.parcel_dir_in_home.clone());
+			let drop_privileges = options.drop_privileges;
+			limits.run(move || user_parcel_paths(&homedir, &parcel_in_home, &parcel_dir_in_home, drop_privileges))
+		};
+		let homepaths = match homepaths {
+			Ok(homepaths) => homepaths,
+			Err(skipped) => {
+				report.add(owner.clone(), None, ReportKind::Skipped(homedir.clone(), skipped));
+				if options.keep_on_timeout {
+					parcels.extend(keep_old_parcels(old.parcels_by(username), report));
+				}
+				continue;
+			}
+		};
+		for (path, name) in homepaths {
+			parcels.extend(read_user_parcel(options, &limits, charset, old, &homedir, &path, name, &owner, report));
+		}
+		transfers.extend(read_transfers(options, &limits, &homedir, &owner, report));
+	}
+
+	let publicpaths = options.public_parcels.iter()
+		.flat_map(|dir| parcel_files(dir).expect("Failed to read public plot directory"));
+	for path in publicpaths {
+		parcels.extend(read_parcel(options, &limits, charset, &path, None, Owner::Public, report).ok().flatten());
+	}
+
+	let rules = BuildRules {
+		quota: options.plot_quota,
+		transfers,
+		charset: charset.clone()
+	};
+	Cadastre::build(old, parcels.into_iter(), &rules, report)
+}
+
+/// All parcel files of a user: the main parcel file, followed by the parcel files in the parcel directory, ordered by name
+/// Parcels from the parcel directory are named after their file so their tenancy can be tracked separately
+/// A missing main parcel file is skipped when it is read, so it isn't looked up here
+/// With dropped privileges the parcel directory is listed as the owner of the home dir, like the files are read
+fn user_parcel_paths(homedir: &Path, parcel_in_home: &Path, parcel_dir_in_home: &Path, drop_privileges: bool) -> Vec<(PathBuf, Option<String>)> {
+	let mut paths = vec![(homedir.join(parcel_in_home), None)];
+	let parcel_dir = homedir.join(parcel_dir_in_home);
+	let files = if drop_privileges {
+		privsep::list_home_dir_as_owner(homedir, &parcel_dir).ok()
+	} else {
+		list_dir(&parcel_dir).ok()
+	};
+	paths.extend(
+		prcl_files(files.unwrap_or_default())
+			.into_iter()
+			.filter_map(|path| {
+				let name = path.file_stem()?.to_str()?.to_string();
+				Some((path, Some(name)))
+			})
+	);
+	paths
+}
+
+/// All files with the prcl extension in a directory, ordered by name
+fn parcel_files(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+	Ok(prcl_files(list_dir(dir)?))
+}
+
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+	Ok(fs::read_dir(dir)?
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.collect())
+}
+
+fn prcl_files(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+	let mut paths: Vec<PathBuf> = paths.into_iter()
+		.filter(|path| path.extension().is_some_and(|ext| ext == "prcl"))
+		.collect();
+	paths.sort();
+	paths
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_user_parcel(options: &UpdateOptions, limits: &Limits, charset: &Charset, old: &Cadastre, homedir: &Path, path: &Path, name: Option<String>, owner: &Owner, report: &mut Report) -> Option<Parcel> {
+	let old_parcel = old.parcels_by(owner.quota_holder()?)
+		.find(|parcel| parcel.name == name);
+	let text = match read_text(options, limits, path, Some(homedir), owner, report) {
+		Ok(text) => text?,
+		Err(_) => {
+			if !options.keep_on_timeout {
+				return None;
+			}
+			return keep_old_parcels(old_parcel.into_iter(), report).pop();
+		}
+	};
+	let parcel = match parse_parcel(&text, path, owner, charset, report) {
+		Ok(parcel) => Parcel { name, ..parcel },
+		Err(parse_err) => {
+			report.add(owner.clone(), None, ReportKind::ParseError(format!("{:?}: {}", path, parse_err)));
+			keep_stale_parcel(options, old_parcel?, report)?
+		}
+	};
+	// a kept parcel goes through the same checks, so co-owners can still withdraw and inactive users still expire
+	let parcel = confirm_co_owners(options, limits, parcel, report);
+	check_activity(options, limits, homedir, path, parcel, report)
+}
+
+/// When the files of a user can't be read in time, their previous parcels can be kept
+fn keep_old_parcels<'a>(old_parcels: impl Iterator<Item=&'a Parcel>, report: &mut Report) -> Vec<Parcel> {
+	old_parcels
+		.map(|parcel| {
+			report.add(parcel.owner.clone(), Some(parcel.location), ReportKind::Retained);
+			parcel.clone()
+		})
+		.collect()
+}
+
+/// When a parcel file is broken, the last working version can be kept for a while so the user doesn't lose their plot
+fn keep_stale_parcel(options: &UpdateOptions, old_parcel: &Parcel, report: &mut Report) -> Option<Parcel> {
+	let max_runs = options.sticky_runs?;
+	if old_parcel.stale >= max_runs {
+		report.add(old_parcel.owner.clone(), Some(old_parcel.location), ReportKind::StaleExpired(old_parcel.stale));
+		return None;
+	}
+	let stale = old_parcel.stale + 1;
+	report.add(old_parcel.owner.clone(), Some(old_parcel.location), ReportKind::Stale(stale));
+	Some(Parcel { stale, ..old_parcel.clone() })
+}
+
+/// Parcels of users that haven't touched their parcel file or their home dir for too long are marked inactive or dropped
+/// Symlinks are not followed, so a user can't borrow the modification time of a file outside their home dir
+/// Looking up the times can hang like reading a file, so it has the same timeout
+fn check_activity(options: &UpdateOptions, limits: &Limits, homedir: &Path, path: &Path, parcel: Parcel, report: &mut Report) -> Option<Parcel> {
+	let Some(max_days) = options.inactive_after_days else { return Some(parcel) };
+	let paths = [path.to_path_buf(), homedir.to_path_buf()];
+	let last_active = limits.run(move ||
+		paths.iter()
+			.filter_map(|p| fs::symlink_metadata(p).and_then(|meta| meta.modified()).ok())
+			.max()
+	);
+	// without any modification time the user gets the benefit of the doubt
+	let Ok(Some(last_active)): Result<Option<SystemTime>, _> = last_active else {
+		report.add(parcel.owner.clone(), Some(parcel.location), ReportKind::UnknownActivity);
+		return Some(parcel);
+	};
+	let days = SystemTime::now()
+		.duration_since(last_active)
+		.unwrap_or_default()
+		.as_secs() / (24 * 60 * 60);
+	if days < max_days {
+		return Some(parcel);
+	}
+	match options.inactive_policy {
+		InactivePolicy::Mark => {
+			report.add(parcel.owner.clone(), Some(parcel.location), ReportKind::Inactive(days));
+			Some(Parcel { inactive: true, ..parcel })
+		}
+		InactivePolicy::Drop => {
+			report.add(parcel.owner, Some(parcel.location), ReportKind::Expired(days));
+			None
+		}
+	}
+}
+
+/// Users only become co-owner of a shared parcel if they list the name of the user who made the parcel in their confirmation file
+/// Co-owners that did not confirm are removed from the parcel
+fn confirm_co_owners(options: &UpdateOptions, limits: &Limits, parcel: Parcel, report: &mut Report) -> Parcel {
+	let Owner::Shared(names) = &parcel.owner else { return parcel };
+	let author = names[0].clone();
+	let mut confirmed = vec![author.clone()];
+	for name in &names[1..] {
+		let is_confirmed = !name.contains('/')
+			&& !name.starts_with('.')
+			&& check_user_access(options, &Owner::user(name)).is_none()
+			&& read_home_file(options, limits, &options.homedirs.join(name), &options.homedirs.join(name).join(&options.share_confirmation_in_home))
+				.is_ok_and(|result| result.is_ok_and(|text| text.split_whitespace().any(|confirmed_author| confirmed_author == author)));
+		if is_confirmed {
+			confirmed.push(name.clone());
+		} else {
+			report.add(Owner::user(&author), Some(parcel.location), ReportKind::UnconfirmedCoOwner(name.clone()));
+		}
+	}
+	let owner = if confirmed.len() > 1 {
+		Owner::Shared(confirmed)
+	} else {
+		Owner::User(author)
+	};
+	Parcel { owner, ..parcel }
+}
+
+fn read_transfers(options: &UpdateOptions, limits: &Limits, homedir: &Path, owner: &Owner, report: &mut Report) -> Vec<Transfer> {
+	let Owner::User(name) = owner else { return Vec::new() };
+	let Ok(Some(text)) = read_text(options, limits, &homedir.join(&options.transfer_in_home), Some(homedir), owner, report) else {
+		return Vec::new()
+	};
+	Transfer::from_text(name, &text).unwrap_or_else(|line| {
+		report.add(owner.clone(), None, ReportKind::InvalidTransfer(line));
+		Vec::new()
+	})
+}
+
+/// Check the deny list and allow list before reading anything from a users home
+fn check_user_access(options: &UpdateOptions, owner: &Owner) -> Option<ReportKind> {
+	let Owner::User(name) = owner else { return None };
+	if options.denied_users.contains(name) {
+		Some(ReportKind::Denied)
+	} else if !options.allowed_users.is_empty() && !options.allowed_users.contains(name) {
+		Some(ReportKind::NotAllowed)
+	} else {
+		None
+	}
+}
+
+fn read_home_file(options: &UpdateOptions, limits: &Limits, homedir: &Path, path: &Path) -> Result<Result<String, FileError>, Skipped> {
+	let (homedir, path) = (homedir.to_path_buf(), path.to_path_buf());
+	let (max_file_size, drop_privileges) = (options.max_file_size, options.drop_privileges);
+	limits.run(move ||
+		if drop_privileges {
+			privsep::read_home_file_as_owner(&homedir, &path, max_file_size)
+		} else {
+			safefile::read_home_file(&homedir, &path, max_file_size)
+		}
+	)
+}
+
+/// Read a file, making sure that files in home dirs can't be used to read anything outside that home dir
+/// Missing files are not an error since users don't have to create every file
+/// Files that could not be read in time are reported and returned as error, so the caller can decide to keep older data
+fn read_text(options: &UpdateOptions, limits: &Limits, path: &Path, homedir: Option<&Path>, owner: &Owner, report: &mut Report) -> Result<Option<String>, Skipped> {
+	let result = match homedir {
+		Some(homedir) => read_home_file(options, limits, homedir, path),
+		None => {
+			let (path, max_file_size) = (path.to_path_buf(), options.max_file_size);
+			limits.run(move || safefile::read_file(&path, max_file_size))
+		}
+	};
+	match result {
+		Ok(Ok(text)) => Ok(Some(text)),
+		Ok(Err(FileError::NotFound)) => Ok(None),
+		Ok(Err(FileError::Io(err))) => {
+			report.add(owner.clone(), None, ReportKind::ReadError(format!("{:?}: {}", path, err)));
+			Ok(None)
+		}
+		Ok(Err(err)) => {
+			report.add(owner.clone(), None, ReportKind::UnsafeFile(path.to_path_buf(), err));
+			Ok(None)
+		}
+		Err(skipped) => {
+			report.add(owner.clone(), None, ReportKind::Skipped(path.to_path_buf(), skipped));
+			Err(skipped)
+		}
+	}
+}
+
+/// Parse a parcel file, reporting the characters that had to be replaced
+fn parse_parcel(text: &str, path: &Path, owner: &Owner, charset: &Charset, report: &mut Report) -> Result<Parcel, ParseError> {
+	let (parcel, replacements) = Parcel::from_text_with_charset(text, owner.clone(), charset)?;
+	if !replacements.is_empty() {
+		report.add(owner.clone(), Some(parcel.location), ReportKind::ReplacedCharacters(path.to_path_buf(), replacements));
+	}
+	Ok(parcel)
+}
+
+fn read_parcel(options: &UpdateOptions, limits: &Limits, charset: &Charset, path: &Path, homedir: Option<&Path>, owner: Owner, report: &mut Report) -> Result<Option<Parcel>, Skipped> {
+	let Some(text) = read_text(options, limits, path, homedir, &owner, report)? else { return Ok(None) };
+	match parse_parcel(&text, path, &owner, charset, report) {
+		Ok(parcel) => Ok(Some(parcel)),
+		Err(parse_err) => {
+			report.add(owner, None, ReportKind::ParseError(format!("{:?}: {}", path, parse_err)));
+			Ok(None)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		pos::Pos,
+		report::ReportEntry,
+		safefile::tests::{test_dir, make_fifo}
+	};
+
+	fn limits() -> Limits {
+		Limits { read_timeout: Duration::from_secs(10), deadline: None }
+	}
+
+	/// Read the parcel file of troido, where the previous update had a parcel that was stale for the given number of updates
+	fn read_with_stale(name: &str, text: &str, stale: u32, options: UpdateOptions) -> (Option<Parcel>, Report) {
+		let homedirs = test_dir(name);
+		let homedir = homedirs.join("troido");
+		fs::create_dir_all(homedir.join(".cadastre")).unwrap();
+		let path = homedir.join(".cadastre/home.txt");
+		fs::write(&path, text).unwrap();
+		let options = UpdateOptions { homedirs, ..options };
+		let limits = limits();
+		let owner = Owner::user("troido");
+		let old_parcel = Parcel { stale, ..Parcel::from_text("3 4\nold", owner.clone()).unwrap() };
+		let old = Cadastre::empty().with_parcel(old_parcel);
+		let mut report = Report::new();
+		let parcel = read_user_parcel(&options, &limits, &Charset::default(), &old, &homedir, &path, None, &owner, &mut report);
+		(parcel, report)
+	}
+
+	#[test]
+	fn skip_denied_and_not_allowed_users() {
+		let homedirs = test_dir("access-lists");
+		for user in ["troido", "odiort", "john"] {
+			fs::create_dir_all(homedirs.join(user).join(".cadastre")).unwrap();
+		}
+		fs::write(homedirs.join("troido/.cadastre/home.txt"), "1 1\ntroido").unwrap();
+		fs::write(homedirs.join("odiort/.cadastre/home.txt"), "2 2\nodiort").unwrap();
+		// a parcel file that can't be opened would stall the run if the home dir were read
+		make_fifo(&homedirs.join("john/.cadastre/home.txt"));
+		let old = Cadastre::empty()
+			.with_parcel(Parcel::empty(Owner::user("troido"), Pos::new(1, 1)))
+			.with_parcel(Parcel::empty(Owner::user("john"), Pos::new(3, 3)));
+		let options = UpdateOptions {
+			homedirs,
+			denied_users: vec!["troido".to_string(), "john".to_string()],
+			read_timeout: Duration::from_millis(200),
+			..UpdateOptions::default()
+		};
+		let mut report = Report::new();
+		let cadastre = generate_cadastre(&options, &old, &mut report);
+		assert_eq!(cadastre.owner_of(Pos::new(1, 1)), None);
+		assert_eq!(cadastre.owner_of(Pos::new(2, 2)), Some(Owner::user("odiort")));
+		let mut entries = report.entries.clone();
+		entries.sort_by_key(|entry| entry.location.map(|pos| pos.x));
+		assert_eq!(entries, vec![
+			ReportEntry { owner: Owner::user("troido"), location: Some(Pos::new(1, 1)), kind: ReportKind::Denied },
+			ReportEntry { owner: Owner::user("john"), location: Some(Pos::new(3, 3)), kind: ReportKind::Denied },
+		]);
+
+		let options = UpdateOptions { denied_users: Vec::new(), allowed_users: vec!["troido".to_string()], ..options };
+		let mut report = Report::new();
+		let cadastre = generate_cadastre(&options, &old, &mut report);
+		assert_eq!(cadastre.owner_of(Pos::new(1, 1)), Some(Owner::user("troido")));
+		assert_eq!(cadastre.owner_of(Pos::new(2, 2)), None);
+		assert_eq!(kinds(&report), vec![&ReportKind::NotAllowed]);
+	}
+
+	fn sticky() -> UpdateOptions {
+		UpdateOptions { sticky_runs: Some(2), ..UpdateOptions::default() }
+	}
+
+	fn kinds(report: &Report) -> Vec<&ReportKind> {
+		report.entries.iter().map(|entry| &entry.kind).collect()
+	}
+
+	#[test]
+	fn keep_broken_parcel_while_sticky() {
+		let (parcel, report) = read_with_stale("sticky-keep", "broken", 1, sticky());
+		let parcel = parcel.unwrap();
+		assert_eq!(parcel.stale, 2);
+		assert_eq!(parcel.art[0].trim_end(), "old");
+		assert_eq!(parcel.location, Pos::new(3, 4));
+		assert!(matches!(kinds(&report)[..], [ReportKind::ParseError(_), ReportKind::Stale(2)]));
+	}
+
+	#[test]
+	fn expire_broken_parcel_after_sticky_runs() {
+		let (parcel, report) = read_with_stale("sticky-expire", "broken", 2, sticky());
+		assert_eq!(parcel, None);
+		assert!(matches!(kinds(&report)[..], [ReportKind::ParseError(_), ReportKind::StaleExpired(2)]));
+	}
+
+	#[test]
+	fn reset_stale_count_when_fixed() {
+		let (parcel, report) = read_with_stale("sticky-reset", "3 4\nnew", 2, sticky());
+		let parcel = parcel.unwrap();
+		assert_eq!(parcel.stale, 0);
+		assert_eq!(parcel.art[0].trim_end(), "new");
+		assert!(report.entries.is_empty());
+	}
+
+	#[test]
+	fn check_activity_of_kept_parcel() {
+		let (parcel, report) = read_with_stale("sticky-inactive", "broken", 0, UpdateOptions { inactive_after_days: Some(0), ..sticky() });
+		assert!(parcel.unwrap().inactive);
+		assert!(matches!(kinds(&report)[..], [ReportKind::ParseError(_), ReportKind::Stale(1), ReportKind::Inactive(0)]));
+	}
+
+	#[test]
+	fn keep_parcel_when_activity_is_unknown() {
+		let homedirs = test_dir("unknown-activity");
+		let options = UpdateOptions { inactive_after_days: Some(0), inactive_policy: InactivePolicy::Drop, ..UpdateOptions::default() };
+		let parcel = Parcel::from_text("3 4\nhello", Owner::user("troido")).unwrap();
+		let mut report = Report::new();
+		let missing = homedirs.join("troido");
+		assert_eq!(check_activity(&options, &limits(), &missing, &missing.join("home.txt"), parcel.clone(), &mut report), Some(parcel));
+		assert_eq!(kinds(&report), vec![&ReportKind::UnknownActivity]);
+	}
+
+	#[test]
+	fn activity_does_not_follow_symlinks() {
+		let homedirs = test_dir("activity-symlink");
+		let homedir = homedirs.join("troido");
+		fs::create_dir(&homedir).unwrap();
+		let path = homedir.join("home.txt");
+		// the target is modified all the time, but the user hasn't touched the link or their home dir in ten days
+		std::os::unix::fs::symlink(homedirs.join("busy.log"), &path).unwrap();
+		fs::write(homedirs.join("busy.log"), "").unwrap();
+		let old = libc::timespec { tv_sec: (SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60)).duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as libc::time_t, tv_nsec: 0 };
+		for p in [&path, &homedir] {
+			let cpath = std::ffi::CString::new(p.as_os_str().as_encoded_bytes()).unwrap();
+			assert_eq!(unsafe { libc::utimensat(libc::AT_FDCWD, cpath.as_ptr(), [old, old].as_ptr(), libc::AT_SYMLINK_NOFOLLOW) }, 0);
+		}
+		let options = UpdateOptions { inactive_after_days: Some(5), inactive_policy: InactivePolicy::Drop, ..UpdateOptions::default() };
+		let parcel = Parcel::from_text("3 4\nhello", Owner::user("troido")).unwrap();
+		let mut report = Report::new();
+		let kept = check_activity(&options, &limits(), &homedir, &path, parcel, &mut report);
+		fs::remove_dir_all(&homedirs).unwrap();
+		assert_eq!(kept, None);
+		assert_eq!(kinds(&report), vec![&ReportKind::Expired(10)]);
+	}
+}